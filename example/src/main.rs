@@ -1,6 +1,41 @@
-use ndjsonlogger::{debug, error, info, warn};
+// Compiled out lines, e.g. debug! and trace! in release builds, leave the
+// values they would log unused
+#![allow(unused_variables, unused_assignments, dead_code)]
+
+use ndjsonlogger::{
+    critical, custom_level, debug, enabled, error, fatal, fields, info, install_panic_hook,
+    instrument, log, log_err, notice, timed, trace, warn, with_fields,
+};
+use ndjsonloggercore::Level;
+use ndjsonloggercore::LogResultExt;
+
+// trace masks - one bit per subsystem
+const TRACE_DB: u64 = 1 << 0;
+const TRACE_NET: u64 = 1 << 1;
+const TRACE_CACHE: u64 = 1 << 2;
+
+// Custom levels sit between the built in ones by severity, audit is just
+// above notice
+custom_level!(AUDIT, "audit", 11);
+
+// Access log lines are written to stderr, apart from the application's lines
+static ACCESS_LOG: ndjsonloggercore::StderrSink = ndjsonloggercore::StderrSink;
+
+const SHUTDOWN_MSG: &str = "example ndjsonlogger finished";
+
+struct SearchResult<'a> {
+    id: u32,
+    score: f64,
+    label: &'a str,
+}
 
 fn main() {
+    // Lines go to stdout unless another sink is registered, e.g.
+    // ndjsonloggercore::StderrSink or a ndjsonloggercore::WriterSink over a file
+    ndjsonloggercore::set_sink(ndjsonloggercore::StdoutSink).expect("sink already set");
+    // Sinks may also be registered by name for lines which choose them
+    ndjsonloggercore::register_sink("audit", ndjsonloggercore::StderrSink);
+
     info!("example ndjsonlogger started");
 
     // debug! lines compile out to no-op in release builds
@@ -16,6 +51,12 @@ fn main() {
     // We may log a single ident providing it is an ident
     let reason = "resource_id does not exist";
     error!("couldn't complete request", { reason });
+
+    // Stable event codes may be given to call sites, a code may only be
+    // used by one call site in a crate
+    error!(code = "EXAMPLE-0001", "login failed", {
+        reason = "bad password"
+    });
     // JSON types
     let request_type = "fetch image";
     let status_code: u16 = 200;
@@ -31,6 +72,30 @@ fn main() {
         a_float            : f32  = 1.2456_f32
     });
 
+    // Owned and shared strings are borrowed, not moved or cloned, when
+    // their type is given. Untyped values are &str expressions, so anything
+    // which derefs to a str may be borrowed with &
+    let owner = String::from("ops-team");
+    let region: std::sync::Arc<str> = "eu-west-1".into();
+    let zone = std::sync::Arc::new(String::from("eu-west-1a"));
+    let aliases = [String::from("primary"), String::from("blue")];
+    info!("deployment target", {
+        owner     : String              = owner,
+        region    : std::sync::Arc<str> = region,
+        zone      = &zone,
+        [aliases  : String              = aliases]
+    });
+
+    // 128 bit integers are written as strings beyond the JSON safe integer
+    // range, any integer may be forced to a string with `as str`
+    let order_id: u128 = 0xc3bd_4f12_9a7e_4b55_8d21_0e6f_a1b2_c3d4;
+    let offset: isize = -12;
+    info!("order placed", {
+        order_id : u128        = order_id,
+        trace_id : u128 as str = 42_u128,
+        offset   : isize       = offset
+    });
+
     // JSON null and Option
     let mut needed_id: Option<&str> = None;
     warn!("needed_id is unknown", {
@@ -54,16 +119,62 @@ fn main() {
         [keys                                 = ["key1", "key2"]]
     });
 
-    // Primative types may be an Option - arrays may not be
+    // Primative types may be an Option
     let int: Option<u32> = None;
     let float: Option<f64> = None;
     let s: Option<&str> = None;
-    info!("all primative types may be Options, arrays cannot be", {
+    info!("all primative types may be Options", {
         int   : Option<u32>  = int,
         float : Option<f64>  = float,
         s     : Option<&str> = s
     });
 
+    // Array elements may be Options, and so may the array itself
+    let retries = [Some(1_u32), None, Some(3)];
+    let names = [Some("alice"), None];
+    let tags: Option<&[&str]> = None;
+    let scores: Option<&[f64]> = Some(&[0.5, 0.75]);
+    info!("arrays may be or contain Options", {
+        [retries : Option<u32>     = retries],
+        [names   : Option<&str>    = names],
+        [tags    : Option<&[&str]> = tags],
+        [scores  : Option<&[f64]>  = scores]
+    });
+
+    // Arrays of arrays, and arrays of objects built from the fields of
+    // each element
+    let matrix = [[1_i64, 0], [0, 1]];
+    let results = [
+        SearchResult {
+            id: 7,
+            score: 0.92,
+            label: "exact",
+        },
+        SearchResult {
+            id: 12,
+            score: 0.41,
+            label: "fuzzy",
+        },
+    ];
+    info!("search done", {
+        [matrix  : &[i64]                         = matrix],
+        [results : { id: u32, score: f64, label } = results]
+    });
+
+    // Nested objects, in place of dotted keys
+    let method = "GET";
+    let status = 200_u16;
+    info!("request served", {
+        http = {
+            method,
+            status : u16 = status,
+            headers = {
+                host = "example.com",
+                [accept = ["text/html", "application/json"]]
+            }
+        }
+    });
+
     // We try to support special values gracefully
     info!("special values", {
             // This will serialize as a string
@@ -71,4 +182,213 @@ fn main() {
             // Put tab character into key
             "odd_\t_key" = "boo to a goose\r\nand you!\r\n"
     });
+
+    // Messages may be format strings capturing variables inline,
+    // the unformatted template is logged as msg_template
+    let user_id = 42_u64;
+    let attempts = 3_u32;
+    warn!("user {user_id} failed login after {attempts} tries", {
+        user_id  : u64 = user_id,
+        attempts : u32 = attempts
+    });
+
+    // Any &str expression may be the message, not just a literal
+    let err_msg = String::from("disk almost full");
+    warn!(&err_msg, { mount = "/var" });
+    info!(concat!("built for ", env!("CARGO_PKG_NAME")));
+
+    // log! takes the level as an expression, so it may be decided at runtime
+    for attempt in 1..=3_u32 {
+        let level = if attempt < 3 {
+            Level::Info
+        } else {
+            Level::Error
+        };
+        log!(level, "connection attempt failed", {
+            attempt : u32 = attempt
+        });
+    }
+
+    // syslog style levels, and custom levels logged with log!
+    notice!("config reloaded");
+    critical!("replica lagging", { lag_s : u32 = 92 });
+    fatal!("data directory missing, exiting");
+    log!(AUDIT, sink = "audit", "user deleted", { user_id : u64 = 42 });
+
+    // Lines below the runtime max level aren't printed, and their entry
+    // values aren't evaluated
+    ndjsonloggercore::set_max_level(Level::Info);
+    debug!("expensive entries skipped", {
+        summary = &expensive_summary()
+    });
+    // enabled! guards preparation done outside of the macro
+    if enabled!(Level::Debug) {
+        let summary = expensive_summary();
+        debug!("summary prepared", { summary = &summary });
+    }
+    // Only levels at least as severe as warn print, so audit doesn't
+    ndjsonloggercore::set_max_level(Level::Warn);
+    log!(AUDIT, "user created", { user_id : u64 = 43 });
+    ndjsonloggercore::set_max_level(Level::Trace);
+
+    // Call sites may be rate limited. Lines suppressed since the call site
+    // last printed are counted in the suppressed key of the next line
+    for n in 0..20_u32 {
+        warn!(once, "cache miss storm started");
+        info!(first = 2, "slow request", { n : u32 = n });
+        warn!(every = "100ms", "queue is full", { n : u32 = n });
+
+        if n == 9 {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+    }
+
+    // Sample high volume lines, sample_by keeps or drops every line for
+    // a key together. The sample rate is logged with each line
+    for request_id in 0..100_u32 {
+        info!(sample = 0.01, "request handled", { request_id : u32 = request_id });
+    }
+    for request_id in 0..20_u32 {
+        let trace_id = format!("trace-{}", request_id % 4);
+        info!(sample = 0.5, sample_by = trace_id, "request traced", {
+            trace_id = &trace_id
+        });
+    }
+
+    // timed! runs a block, returns its value and logs how long it took.
+    // warn_above escalates the line to warn when the block is slow
+    let table = "users";
+    let rows: u32 = timed!(info, "db query", { table }, {
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        12
+    });
+    timed!(info, warn_above = "1ms", "db query", { table, rows : u32 = rows }, {
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    });
+    // ? inside the block would leave before the line is logged, so return
+    // the Result from the block and handle it outside
+    let parsed = timed!(info, "parse rows", { table }, { "12".parse::<u32>() });
+    info!("rows parsed", { ok: bool = parsed.is_ok() });
+
+    // log_err! logs Err results with the error and its source chain,
+    // then returns the result unchanged
+    let port = log_err!("80a".parse::<u16>(), "invalid port", { port = "80a" });
+    let _ = log_err!(read_config(), "couldn't read config");
+    // .log_err chains onto a Result
+    let _ = open_config().log_err("couldn't open config");
+    let _ = read_config().log_err("couldn't read config");
+    info!("port parsed", { ok : bool = port.is_ok() });
+
+    // #[instrument] logs entering and leaving a fn
+    let _ = load_user(42, "alice");
+    let _ = first_word("hello world");
+
+    // trace! lines only compile in with the trace feature, and only
+    // print when their mask overlaps the process-wide trace mask
+    ndjsonloggercore::set_trace_mask(TRACE_DB | TRACE_CACHE);
+    trace!(TRACE_DB, "query planned", { table = "users" });
+    // This won't print - TRACE_NET isn't in the mask
+    trace!(TRACE_NET, "socket readable");
+    trace!(TRACE_NET | TRACE_CACHE, "cache refreshed from upstream");
+
+    // Context fields are added to every line logged in their scope
+    let request_id = "req-7f3a";
+    let uid = 42;
+    with_fields!({ request_id, user_id : u64 = uid }, {
+        info!("request started");
+        let _guard = fields!({ stage = "auth" }).enter();
+        warn!("token near expiry", { expires_s : u32 = 30 });
+    });
+    info!("request finished");
+
+    // sink = writes the line to another sink than the registered one
+    info!(sink = ACCESS_LOG, "GET /healthz", { status : u16 = 200 });
+
+    // Loggers carry bound fields, lines are routed through one with =>
+    let billing = ndjsonloggercore::Logger::new().child(fields!({ component = "billing" }));
+    let invoices = billing.child(fields!({ subsystem = "invoices" }));
+    info!(billing => "charge captured", { amount_cents : u64 = 1999 });
+    warn!(invoices => once, "invoice overdue", { invoice_id = "INV-1042" });
+    let _ = log_err!(open_config(), invoices => "couldn't load invoice template");
+
+    // Futures carry their fields with them, even when polled on other threads
+    let upload = with_fields!({ request_id = "req-9c01" }, async move {
+        info!("upload started");
+        let size = store_upload().await;
+        info!("upload stored", { size : u64 = size });
+    });
+    block_on(upload);
+
+    // install_panic_hook! logs panics as error lines instead of text on stderr
+    install_panic_hook!();
+    let worker = std::thread::Builder::new()
+        .name("worker".to_string())
+        .spawn(|| panic!("worker gave up after {} retries", 3))
+        .unwrap();
+    let _ = worker.join();
+
+    info!(SHUTDOWN_MSG);
+}
+
+async fn store_upload() -> u64 {
+    4096
+}
+
+// Polls a future to completion on the current thread, standing in for an
+// async runtime
+fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+    let mut fut = std::pin::pin!(fut);
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    loop {
+        if let std::task::Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return out;
+        }
+        std::thread::yield_now();
+    }
+}
+
+// Stands in for work we only want to do if the line will be printed
+fn expensive_summary() -> String {
+    (0..1000)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[instrument(level = "debug", fields(user_id, name))]
+fn load_user(user_id: u64, name: &str) -> Result<u32, ConfigError> {
+    if name.is_empty() {
+        return Ok(0);
+    }
+    open_config()?;
+    Ok(user_id as u32)
+}
+
+#[instrument(fields(text))]
+fn first_word(text: &str) -> &str {
+    text.split(' ').next().unwrap_or(text)
+}
+
+fn read_config() -> Result<String, Box<dyn std::error::Error>> {
+    Ok(open_config()?)
+}
+
+fn open_config() -> Result<String, ConfigError> {
+    let err = std::io::Error::other("permission denied");
+    Err(ConfigError(err))
+}
+
+#[derive(Debug)]
+struct ConfigError(std::io::Error);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "config unreadable")
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
 }
@@ -0,0 +1,279 @@
+extern crate std;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{self, Poll};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::string::{String, ToString};
+use std::sync::Arc;
+use std::thread_local;
+use std::vec::Vec;
+
+use crate::{Atom, Entry, Level, Sink, Value};
+
+// Context fields outlive the values they were built from, so atoms are
+// copied into an owned form
+enum OwnedAtom {
+    Float(f64),
+    Int(i64),
+    Uint(u64),
+    String(String),
+    Bool(bool),
+    Null,
+    Int128(i128),
+    Uint128(u128),
+    StrInt(i128),
+    StrUint(u128),
+}
+
+impl OwnedAtom {
+    fn new(atom: Atom) -> Self {
+        match atom {
+            Atom::Float(f) => OwnedAtom::Float(f),
+            Atom::Int(i) => OwnedAtom::Int(i),
+            Atom::Uint(u) => OwnedAtom::Uint(u),
+            Atom::String(s) => OwnedAtom::String(s.to_string()),
+            Atom::Bool(b) => OwnedAtom::Bool(b),
+            Atom::Null => OwnedAtom::Null,
+            Atom::Int128(i) => OwnedAtom::Int128(i),
+            Atom::Uint128(u) => OwnedAtom::Uint128(u),
+            Atom::StrInt(i) => OwnedAtom::StrInt(i),
+            Atom::StrUint(u) => OwnedAtom::StrUint(u),
+        }
+    }
+
+    fn atom(&self) -> Atom<'_> {
+        match self {
+            OwnedAtom::Float(f) => Atom::Float(*f),
+            OwnedAtom::Int(i) => Atom::Int(*i),
+            OwnedAtom::Uint(u) => Atom::Uint(*u),
+            OwnedAtom::String(s) => Atom::String(s),
+            OwnedAtom::Bool(b) => Atom::Bool(*b),
+            OwnedAtom::Null => Atom::Null,
+            OwnedAtom::Int128(i) => Atom::Int128(*i),
+            OwnedAtom::Uint128(u) => Atom::Uint128(*u),
+            OwnedAtom::StrInt(i) => Atom::StrInt(*i),
+            OwnedAtom::StrUint(u) => Atom::StrUint(*u),
+        }
+    }
+}
+
+enum OwnedValue {
+    Atom(OwnedAtom),
+    Optatom(Option<OwnedAtom>),
+}
+
+// A set of key/value pairs added to every line logged while they're entered.
+// Cloning is cheap, the fields are shared.
+#[derive(Clone)]
+pub struct Fields(Arc<Vec<(&'static str, OwnedValue)>>);
+
+impl Fields {
+    // Only atoms and optional atoms may be context fields, arrays and
+    // objects panic
+    pub fn new<'s>(entries: impl Iterator<Item = Entry<'s, 's>>) -> Self {
+        let fields = entries
+            .map(|e| {
+                let value = match e.value {
+                    Value::Atom(a) => OwnedValue::Atom(OwnedAtom::new(a)),
+                    Value::Optatom(oa) => OwnedValue::Optatom(oa.map(OwnedAtom::new)),
+                    _ => panic!("context fields may not be arrays or objects"),
+                };
+                (e.key, value)
+            })
+            .collect();
+
+        Self(Arc::new(fields))
+    }
+
+    // Push the fields onto this thread's context until the guard is dropped
+    pub fn enter(self) -> FieldsGuard {
+        CONTEXT.with(|c| c.borrow_mut().push(self));
+        FieldsGuard {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Vec<Fields>> = const { RefCell::new(Vec::new()) };
+}
+
+// The guard pops from the thread it was entered on, so it must stay there
+pub struct FieldsGuard {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for FieldsGuard {
+    fn drop(&mut self) {
+        CONTEXT.with(|c| c.borrow_mut().pop());
+    }
+}
+
+// A snapshot of the fields entered on this thread, outermost first
+pub struct Context(Vec<Fields>);
+
+pub fn context() -> Context {
+    Context(CONTEXT.with(|c| c.borrow().clone()))
+}
+
+impl Context {
+    pub fn entries(&self) -> impl Iterator<Item = Entry<'_, '_>> {
+        fields_entries(&self.0)
+    }
+}
+
+fn fields_entries(fields: &[Fields]) -> impl Iterator<Item = Entry<'_, '_>> {
+    fields
+        .iter()
+        .flat_map(|f| f.0.iter())
+        .map(|(key, value)| Entry {
+            key,
+            value: match value {
+                OwnedValue::Atom(a) => Value::Atom(a.atom()),
+                OwnedValue::Optatom(oa) => Value::Optatom(oa.as_ref().map(|a| a.atom())),
+            },
+        })
+}
+
+// A future which enters its fields for the duration of every poll. A guard
+// can't be held across an await as the task may move between threads.
+pub struct WithLogFields<F> {
+    fut: F,
+    fields: Fields,
+}
+
+impl<F: Future> Future for WithLogFields<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<F::Output> {
+        // Safety: fut is never moved out of self, it's only polled pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let _guard = this.fields.clone().enter();
+        unsafe { Pin::new_unchecked(&mut this.fut) }.poll(cx)
+    }
+}
+
+pub trait WithLogFieldsExt: Future + Sized {
+    fn with_log_fields(self, fields: Fields) -> WithLogFields<Self> {
+        WithLogFields { fut: self, fields }
+    }
+}
+
+impl<F: Future> WithLogFieldsExt for F {}
+
+// A logger carrying bound fields, which are added to each of its lines
+// ahead of the context fields and the line's own entries
+#[derive(Clone, Default)]
+pub struct Logger {
+    fields: Vec<Fields>,
+}
+
+impl Logger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A new logger with the fields of self followed by fields
+    pub fn child(&self, fields: Fields) -> Logger {
+        let mut child = self.clone();
+        child.fields.push(fields);
+        child
+    }
+
+    pub fn log<'s>(
+        &'s self,
+        msg: &str,
+        level: Level,
+        entries: impl Iterator<Item = Entry<'s, 's>>,
+    ) {
+        crate::sink_log(msg, level, fields_entries(&self.fields).chain(entries));
+    }
+
+    pub fn log_to<'s>(
+        &'s self,
+        sink: &dyn Sink,
+        msg: &str,
+        level: Level,
+        entries: impl Iterator<Item = Entry<'s, 's>>,
+    ) {
+        crate::log_to(
+            sink,
+            msg,
+            level,
+            fields_entries(&self.fields).chain(entries),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys() -> Vec<&'static str> {
+        context().entries().map(|e| e.key).collect()
+    }
+
+    #[test]
+    fn scoped_fields() {
+        let request_id = String::from("req-1");
+        let outer = Fields::new(
+            [Entry {
+                key: "request_id",
+                value: Value::Atom(Atom::String(&request_id)),
+            }]
+            .into_iter(),
+        );
+        drop(request_id);
+
+        let _outer = outer.enter();
+        {
+            let _inner = Fields::new(
+                [Entry {
+                    key: "user_id",
+                    value: Value::Optatom(Some(Atom::Uint(7))),
+                }]
+                .into_iter(),
+            )
+            .enter();
+            assert_eq!(keys(), ["request_id", "user_id"]);
+        }
+        assert_eq!(keys(), ["request_id"]);
+
+        match context().entries().next().map(|e| e.value) {
+            Some(Value::Atom(Atom::String(s))) => assert_eq!(s, "req-1"),
+            _ => panic!("expected the copied request_id"),
+        }
+    }
+
+    #[test]
+    fn future_fields() {
+        use core::task::{RawWaker, RawWakerVTable, Waker};
+
+        fn noop_raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                noop_raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+
+        let fields = Fields::new(
+            [Entry {
+                key: "task",
+                value: Value::Atom(Atom::Bool(true)),
+            }]
+            .into_iter(),
+        );
+        let mut fut = std::boxed::Box::pin(async { keys() }.with_log_fields(fields));
+
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut cx = task::Context::from_waker(&waker);
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(keys) => assert_eq!(keys, ["task"]),
+            Poll::Pending => panic!("expected the future to be ready"),
+        }
+        assert!(keys().is_empty());
+    }
+}
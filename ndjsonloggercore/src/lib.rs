@@ -0,0 +1,26 @@
+#![no_std]
+
+mod logger;
+pub use logger::{
+    level_enabled, log, set_max_level, set_trace_mask, trace_enabled, Atom, Entry, Level, Nested,
+    Outputter, Value,
+};
+
+// Primative to string functions
+mod conv;
+
+#[cfg(feature = "std")]
+mod stdfeatures;
+#[cfg(feature = "std")]
+pub use stdfeatures::{period_elapsed, stdout_log, LogResultExt, StdoutOutputter};
+#[cfg(feature = "std")]
+mod context;
+#[cfg(feature = "std")]
+pub use context::{context, Context, Fields, FieldsGuard, Logger, WithLogFields, WithLogFieldsExt};
+#[cfg(feature = "std")]
+mod sink;
+#[cfg(feature = "std")]
+pub use sink::{
+    log_to, named_sink, register_sink, set_sink, sink_log, MemorySink, SetSinkError, Sink,
+    StderrSink, StdoutSink, WriterSink,
+};
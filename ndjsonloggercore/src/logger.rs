@@ -0,0 +1,733 @@
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::conv::{f64_to_str, i128toa_base10, itoa_base10, u128toa_base10, utoa_base10};
+
+const BUF_SIZE: usize = 24;
+// 128 bit integers need a larger buffer
+const BUF_SIZE_128: usize = 40;
+
+// Number.MAX_SAFE_INTEGER, larger integers lose precision as doubles
+const JSON_MAX_SAFE_INT: u128 = (1 << 53) - 1;
+
+pub enum Atom<'a> {
+    Float(f64),
+    Int(i64),
+    Uint(u64),
+    String(&'a str),
+    Bool(bool),
+    // None elements of arrays of options
+    Null,
+    // Written as numbers within the JSON safe integer range, otherwise as
+    // decimal strings
+    Int128(i128),
+    Uint128(u128),
+    // Always written as decimal strings
+    StrInt(i128),
+    StrUint(u128),
+}
+
+impl<'a> Atom<'a> {
+    fn write_value(&self, outputter: &mut impl Outputter, buf: &mut [u8; BUF_SIZE]) {
+        match self {
+            Atom::Float(f) => outputter.write_str(f64_to_str(buf, *f)),
+            Atom::Int(i) => outputter.write_str(itoa_base10(buf, *i)),
+            Atom::Uint(u) => outputter.write_str(utoa_base10(buf, *u)),
+            Atom::String(s) => outputter.write_json_string(s),
+            Atom::Bool(b) => outputter.write_json_bool(*b),
+            Atom::Null => outputter.write_json_null(),
+            Atom::Int128(i) => {
+                let mut buf128 = [0_u8; BUF_SIZE_128];
+                let s = i128toa_base10(&mut buf128, *i);
+                if i.unsigned_abs() <= JSON_MAX_SAFE_INT {
+                    outputter.write_str(s);
+                } else {
+                    outputter.write_json_string(s);
+                }
+            }
+            Atom::Uint128(u) => {
+                let mut buf128 = [0_u8; BUF_SIZE_128];
+                let s = u128toa_base10(&mut buf128, *u);
+                if *u <= JSON_MAX_SAFE_INT {
+                    outputter.write_str(s);
+                } else {
+                    outputter.write_json_string(s);
+                }
+            }
+            Atom::StrInt(i) => {
+                outputter.write_json_string(i128toa_base10(&mut [0_u8; BUF_SIZE_128], *i))
+            }
+            Atom::StrUint(u) => {
+                outputter.write_json_string(u128toa_base10(&mut [0_u8; BUF_SIZE_128], *u))
+            }
+        }
+    }
+}
+
+pub enum Value<'s, 'a> {
+    Atom(Atom<'a>),
+    Array(&'s mut dyn Iterator<Item = Atom<'a>>),
+    Optatom(Option<Atom<'a>>),
+    Optarray(Option<&'s mut dyn Iterator<Item = Atom<'a>>>),
+    Object(&'s mut [Entry<'s, 'a>]),
+    Nested(&'s mut Nested<'s>),
+    Optnested(Option<&'s mut Nested<'s>>),
+}
+
+// Arrays of arrays or of objects, the function calls its argument with each
+// element in turn
+pub type Nested<'s> = dyn FnMut(&mut dyn FnMut(Value)) + 's;
+
+pub struct Entry<'s, 'a> {
+    pub key: &'static str,
+    pub value: Value<'s, 'a>,
+}
+
+#[derive(Copy, Clone)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Notice,
+    Warn,
+    Error,
+    Critical,
+    Fatal,
+    Custom { name: &'static str, severity: u8 },
+}
+
+impl Level {
+    // Levels are ordered and filtered by severity, higher is more severe.
+    // The gaps leave room for custom levels between the built in ones.
+    pub const fn severity(self) -> u8 {
+        match self {
+            Level::Trace => 1,
+            Level::Debug => 5,
+            Level::Info => 9,
+            Level::Notice => 10,
+            Level::Warn => 13,
+            Level::Error => 17,
+            Level::Critical => 18,
+            Level::Fatal => 21,
+            Level::Custom { severity, .. } => severity,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Notice => "notice",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Critical => "critical",
+            Level::Fatal => "fatal",
+            Level::Custom { name, .. } => name,
+        }
+    }
+}
+
+pub trait Outputter {
+    fn write_str(&mut self, val: &str);
+    fn endline(&mut self);
+
+    fn write_str_with_escape(&mut self, val: &str) {
+        let mut start = 0;
+        for (n, c) in val.char_indices() {
+            if c == '"' {
+                self.write_str(&val[start..n]);
+                self.write_str("\\\"");
+                start = n + 1;
+                continue;
+            }
+
+            if c == '\\' {
+                self.write_str(&val[start..n]);
+                self.write_str("\\\\");
+                start = n + 1;
+                continue;
+            }
+
+            if c == '\n' {
+                self.write_str(&val[start..n]);
+                self.write_str("\\n");
+                start = n + 1;
+                continue;
+            }
+
+            if c == '\r' {
+                self.write_str(&val[start..n]);
+                self.write_str("\\r");
+                start = n + 1;
+                continue;
+            }
+
+            if c == '\t' {
+                self.write_str(&val[start..n]);
+                self.write_str("\\t");
+                start = n + 1;
+                continue;
+            }
+        }
+
+        self.write_str(&val[start..]);
+    }
+
+    fn write_json_comma(&mut self) {
+        self.write_str(",");
+    }
+
+    fn write_json_null(&mut self) {
+        self.write_str("null");
+    }
+
+    fn write_json_key(&mut self, key: &str) {
+        self.write_json_string(key);
+        self.write_str(":");
+    }
+
+    fn write_json_string(&mut self, s: &str) {
+        self.write_str("\"");
+        self.write_str_with_escape(s);
+        self.write_str("\"");
+    }
+
+    fn write_json_bool(&mut self, b: bool) {
+        if b {
+            self.write_str("true");
+        } else {
+            self.write_str("false");
+        }
+    }
+
+    fn write_json_start(&mut self) {
+        self.write_str("{");
+    }
+
+    fn write_json_end(&mut self) {
+        self.write_str("}");
+    }
+
+    fn write_json_start_array(&mut self) {
+        self.write_str("[");
+    }
+
+    fn write_json_end_array(&mut self) {
+        self.write_str("]");
+    }
+}
+
+pub fn log<'s>(
+    service_name: Option<&'static str>,
+    outputter: &mut impl Outputter,
+    msg: &str,
+    level: Level,
+    entries: impl Iterator<Item = Entry<'s, 's>>,
+) {
+    outputter.write_json_start();
+    if let Some(service_name) = service_name {
+        outputter.write_json_key("service");
+        outputter.write_json_string(service_name);
+        outputter.write_json_comma();
+    }
+
+    // Log Level
+    outputter.write_json_key("level");
+    outputter.write_json_string(level.as_str());
+    outputter.write_json_comma();
+
+    // Timestamp
+    #[cfg(feature = "isotimestamp")]
+    write_isotimestamp(outputter);
+
+    // Message
+    outputter.write_json_key("msg");
+    outputter.write_json_string(msg);
+
+    for mut e in entries {
+        // Comma
+        outputter.write_json_comma();
+
+        write_entry(outputter, &mut e);
+    }
+
+    outputter.write_json_end();
+    outputter.endline();
+}
+
+fn write_entry(outputter: &mut impl Outputter, e: &mut Entry) {
+    // key
+    outputter.write_json_key(e.key);
+
+    // value
+    write_value(outputter, &mut e.value);
+}
+
+fn write_value(outputter: &mut impl Outputter, value: &mut Value) {
+    let mut buf = [0_u8; BUF_SIZE];
+
+    match value {
+        Value::Atom(ref a) => a.write_value(outputter, &mut buf),
+        Value::Array(ref mut arr) => write_array(outputter, *arr, &mut buf),
+        Value::Optatom(ref oa) => match oa {
+            Some(a) => a.write_value(outputter, &mut buf),
+            None => outputter.write_json_null(),
+        },
+        Value::Optarray(ref mut oarr) => match oarr {
+            Some(ref mut arr) => write_array(outputter, *arr, &mut buf),
+            None => outputter.write_json_null(),
+        },
+        Value::Object(ref mut obj) => {
+            outputter.write_json_start();
+            for (n, e) in obj.iter_mut().enumerate() {
+                if n != 0 {
+                    outputter.write_json_comma();
+                }
+
+                write_entry(outputter, e);
+            }
+            outputter.write_json_end();
+        }
+        Value::Nested(ref mut nested) => write_nested(outputter, *nested),
+        Value::Optnested(ref mut onested) => match onested {
+            Some(ref mut nested) => write_nested(outputter, *nested),
+            None => outputter.write_json_null(),
+        },
+    }
+}
+
+fn write_nested(outputter: &mut impl Outputter, nested: &mut Nested) {
+    outputter.write_json_start_array();
+    let mut n = 0;
+    nested(&mut |mut v| {
+        if n != 0 {
+            outputter.write_json_comma();
+        }
+        n += 1;
+
+        write_value(outputter, &mut v);
+    });
+    outputter.write_json_end_array();
+}
+
+fn write_array<'a>(
+    outputter: &mut impl Outputter,
+    arr: &mut dyn Iterator<Item = Atom<'a>>,
+    buf: &mut [u8; BUF_SIZE],
+) {
+    outputter.write_json_start_array();
+    for (n, a) in arr.enumerate() {
+        if n != 0 {
+            outputter.write_json_comma();
+        }
+
+        a.write_value(outputter, buf);
+    }
+    outputter.write_json_end_array();
+}
+
+static TRACE_MASK: AtomicU64 = AtomicU64::new(0);
+
+// Set the process-wide trace mask, trace lines are logged when their
+// mask shares a bit with it
+pub fn set_trace_mask(mask: u64) {
+    TRACE_MASK.store(mask, Ordering::Relaxed);
+}
+
+pub fn trace_enabled(mask: u64) -> bool {
+    TRACE_MASK.load(Ordering::Relaxed) & mask != 0
+}
+
+static MIN_SEVERITY: AtomicU8 = AtomicU8::new(0);
+
+// Set the most verbose level logged, every level is logged until set
+pub fn set_max_level(level: Level) {
+    MIN_SEVERITY.store(level.severity(), Ordering::Relaxed);
+}
+
+pub fn level_enabled(level: Level) -> bool {
+    level.severity() >= MIN_SEVERITY.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "isotimestamp")]
+fn write_isotimestamp(outputter: &mut impl Outputter) {
+    use chrono::prelude::*;
+
+    let ts = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+
+    outputter.write_json_key("ts");
+    outputter.write_json_string(&ts);
+    outputter.write_json_comma();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use std::string::String;
+    use std::vec::Vec;
+
+    use random_fast_rng::{FastRng, Random};
+
+    #[derive(Default)]
+    struct Output {
+        inner: String,
+        fin_count: usize,
+    }
+
+    impl Outputter for Output {
+        fn write_str(&mut self, val: &str) {
+            self.inner.push_str(val);
+        }
+
+        fn endline(&mut self) {
+            self.fin_count += 1;
+        }
+    }
+
+    #[test]
+    fn outputter_() {
+        #[derive(serde::Deserialize, PartialEq, Eq, Debug)]
+        struct LogLine {
+            uint: u64,
+            iint: i64,
+            t: bool,
+            f: bool,
+            empty_string: String,
+            single_quote: String,
+            single_newline: String,
+            tab_indent: String,
+            multi_line: String,
+            null: Option<String>,
+            null_array: Option<Vec<String>>,
+        }
+
+        let mut rng = FastRng::new();
+        let uint = rng.gen::<u64>();
+        let iint = rng.gen::<i64>();
+        let empty_string = String::new();
+        let single_quote = String::from("\"");
+        let single_newline = String::from("\n");
+        let multi_line = String::from("\thello\r\n\tMy name is Bob\r\n\t\"boo\"\r\n\tend");
+        let tab_indent = String::from("\thello world");
+        let null = None;
+        let null_array = None;
+        let mut out = Output::default();
+        let mut buf = [0_u8; BUF_SIZE];
+
+        out.write_json_start();
+
+        // uint and iint
+        out.write_json_key("uint");
+        out.write_str(utoa_base10(&mut buf, uint));
+        out.write_json_comma();
+        out.write_json_key("iint");
+        out.write_str(itoa_base10(&mut buf, iint));
+        out.write_json_comma();
+
+        // t and f
+        out.write_json_key("t");
+        out.write_json_bool(true);
+        out.write_json_comma();
+        out.write_json_key("f");
+        out.write_json_bool(false);
+        out.write_json_comma();
+
+        // Strings
+        out.write_json_key("empty_string");
+        out.write_json_string(&empty_string);
+        out.write_json_comma();
+        out.write_json_key("single_quote");
+        out.write_json_string(&single_quote);
+        out.write_json_comma();
+        out.write_json_key("single_newline");
+        out.write_json_string(&single_newline);
+        out.write_json_comma();
+        out.write_json_key("tab_indent");
+        out.write_json_string(&tab_indent);
+        out.write_json_comma();
+        out.write_json_key("multi_line");
+        out.write_json_string(&multi_line);
+
+        // Null value
+        out.write_json_comma();
+        out.write_json_key("null");
+        out.write_json_null();
+        out.write_json_comma();
+        out.write_json_key("null_array");
+        out.write_json_null();
+
+        out.write_json_end();
+        out.endline();
+
+        let log_line: LogLine =
+            serde_json::from_str(&out.inner).expect("couldn't deserialize logline, invalid json");
+
+        assert_eq!(out.fin_count, 1);
+
+        assert_eq!(
+            log_line,
+            LogLine {
+                uint,
+                iint,
+                f: false,
+                t: true,
+                empty_string,
+                single_quote,
+                single_newline,
+                tab_indent,
+                multi_line,
+                null,
+                null_array,
+            }
+        );
+    }
+
+    #[test]
+    fn log_no_entries() {
+        #[derive(serde::Deserialize)]
+        struct LogLine {
+            level: String,
+            msg: String,
+
+            #[cfg(feature = "isotimestamp")]
+            ts: String,
+        }
+
+        let msg = "hello world";
+        let level = Level::Info;
+
+        let mut out = Output::default();
+        log(None, &mut out, msg, level, [].into_iter());
+
+        let log_line: LogLine =
+            serde_json::from_str(&out.inner).expect("couldn't deserialize logline, invalid json");
+
+        assert_eq!(log_line.msg, msg);
+        assert_eq!(log_line.level, level.as_str());
+        #[cfg(feature = "isotimestamp")]
+        assert!(!log_line.ts.is_empty())
+    }
+
+    #[test]
+    fn log_entries() {
+        #[derive(serde::Deserialize)]
+        struct LogLine {
+            level: String,
+            msg: String,
+
+            #[cfg(feature = "isotimestamp")]
+            ts: String,
+
+            resource_id: u64,
+            healthy: bool,
+            some_nums: [i64; 4],
+            two_strings: Vec<String>,
+            null_array: Option<Vec<String>>,
+            opt_array: Option<Vec<String>>,
+            opt_elems: Vec<Option<u64>>,
+            owner: Owner,
+            matrix: Vec<Vec<u64>>,
+            no_matrix: Option<Vec<Vec<u64>>>,
+            safe_int: i64,
+            max_safe_uint: u64,
+            unsafe_uint: String,
+            unsafe_int: String,
+            str_int: String,
+            str_uint: String,
+        }
+
+        #[derive(serde::Deserialize, PartialEq, Eq, Debug)]
+        struct Owner {
+            id: u64,
+            name: String,
+        }
+
+        let mut rng = FastRng::new();
+
+        let msg = "hello world";
+        let level = Level::Info;
+        let resource_id = rng.gen::<u64>();
+        let healthy = true;
+        let some_nums = rng.gen::<[i64; 4]>();
+        let two_strings = [String::from(""), String::from("boo")];
+        let opt_array = [
+            String::from("hello"),
+            String::from("goose"),
+            String::from("world"),
+        ];
+        let opt_elems = [Some(1), None, Some(3)];
+        let owner_id = rng.gen::<u64>();
+        let matrix = [[1, 2], [3, 4]];
+
+        let mut out = Output::default();
+        log(
+            None,
+            &mut out,
+            msg,
+            level,
+            [
+                Entry {
+                    key: "resource_id",
+                    value: Value::Atom(Atom::Uint(resource_id)),
+                },
+                Entry {
+                    key: "healthy",
+                    value: Value::Atom(Atom::Bool(healthy)),
+                },
+                Entry {
+                    key: "some_nums",
+                    value: Value::Array(&mut some_nums.iter().map(|i| Atom::Int(*i))),
+                },
+                Entry {
+                    key: "two_strings",
+                    value: Value::Array(&mut two_strings.iter().map(|s| Atom::String(&s[..]))),
+                },
+                Entry {
+                    key: "null_array",
+                    value: Value::Optarray(None),
+                },
+                Entry {
+                    key: "opt_array",
+                    value: Value::Optarray(Some(
+                        &mut opt_array.iter().map(|s| Atom::String(&s[..])),
+                    )),
+                },
+                Entry {
+                    key: "opt_elems",
+                    value: Value::Array(&mut opt_elems.iter().map(|u| match u {
+                        Some(u) => Atom::Uint(*u),
+                        None => Atom::Null,
+                    })),
+                },
+                Entry {
+                    key: "owner",
+                    value: Value::Object(&mut [
+                        Entry {
+                            key: "id",
+                            value: Value::Atom(Atom::Uint(owner_id)),
+                        },
+                        Entry {
+                            key: "name",
+                            value: Value::Atom(Atom::String("bob")),
+                        },
+                    ]),
+                },
+                Entry {
+                    key: "safe_int",
+                    value: Value::Atom(Atom::Int128(-5)),
+                },
+                Entry {
+                    key: "max_safe_uint",
+                    value: Value::Atom(Atom::Uint128((1 << 53) - 1)),
+                },
+                Entry {
+                    key: "unsafe_uint",
+                    value: Value::Atom(Atom::Uint128(1 << 53)),
+                },
+                Entry {
+                    key: "unsafe_int",
+                    value: Value::Atom(Atom::Int128(i128::MIN)),
+                },
+                Entry {
+                    key: "str_int",
+                    value: Value::Atom(Atom::StrInt(-42)),
+                },
+                Entry {
+                    key: "str_uint",
+                    value: Value::Atom(Atom::StrUint(42)),
+                },
+                Entry {
+                    key: "matrix",
+                    value: Value::Nested(&mut |visit: &mut dyn FnMut(Value)| {
+                        for row in matrix.iter() {
+                            visit(Value::Array(&mut row.iter().map(|u| Atom::Uint(*u))));
+                        }
+                    }),
+                },
+                Entry {
+                    key: "no_matrix",
+                    value: Value::Optnested(None),
+                },
+            ]
+            .into_iter(),
+        );
+
+        let log_line: LogLine =
+            serde_json::from_str(&out.inner).expect("couldn't deserialize logline, invalid json");
+
+        assert_eq!(log_line.msg, msg);
+        assert_eq!(log_line.level, level.as_str());
+        assert_eq!(log_line.resource_id, resource_id);
+        assert_eq!(log_line.healthy, healthy);
+        assert_eq!(log_line.some_nums, some_nums);
+        assert_eq!(log_line.two_strings, two_strings);
+        assert_eq!(log_line.null_array, None);
+        assert_eq!(
+            log_line.opt_array.as_ref().map(|v| &v[..]),
+            Some(&opt_array[..])
+        );
+        assert_eq!(log_line.opt_elems, opt_elems);
+        assert_eq!(log_line.matrix, [[1, 2], [3, 4]]);
+        assert_eq!(log_line.no_matrix, None);
+        assert_eq!(log_line.safe_int, -5);
+        assert_eq!(log_line.max_safe_uint, (1 << 53) - 1);
+        assert_eq!(log_line.unsafe_uint, "9007199254740992");
+        assert_eq!(
+            log_line.unsafe_int,
+            "-170141183460469231731687303715884105728"
+        );
+        assert_eq!(log_line.str_int, "-42");
+        assert_eq!(log_line.str_uint, "42");
+        assert_eq!(
+            log_line.owner,
+            Owner {
+                id: owner_id,
+                name: String::from("bob"),
+            }
+        );
+        #[cfg(feature = "isotimestamp")]
+        assert!(!log_line.ts.is_empty())
+    }
+
+    #[test]
+    fn trace_mask() {
+        const TRACE_DB: u64 = 1 << 0;
+        const TRACE_NET: u64 = 1 << 1;
+
+        set_trace_mask(TRACE_DB);
+        assert!(trace_enabled(TRACE_DB));
+        assert!(trace_enabled(TRACE_DB | TRACE_NET));
+        assert!(!trace_enabled(TRACE_NET));
+
+        set_trace_mask(0);
+        assert!(!trace_enabled(TRACE_DB));
+    }
+
+    #[test]
+    fn max_level() {
+        assert!(level_enabled(Level::Trace));
+
+        set_max_level(Level::Warn);
+        assert!(!level_enabled(Level::Trace));
+        assert!(!level_enabled(Level::Info));
+        assert!(level_enabled(Level::Warn));
+        assert!(level_enabled(Level::Error));
+
+        let audit = Level::Custom {
+            name: "audit",
+            severity: 11,
+        };
+        set_max_level(Level::Notice);
+        assert!(level_enabled(audit));
+        assert!(!level_enabled(Level::Info));
+
+        set_max_level(audit);
+        assert!(!level_enabled(Level::Notice));
+        assert!(level_enabled(Level::Warn));
+
+        set_max_level(Level::Trace);
+        assert!(level_enabled(Level::Trace));
+    }
+}
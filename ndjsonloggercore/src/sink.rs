@@ -0,0 +1,285 @@
+extern crate std;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Write;
+use std::string::String;
+use std::sync::Mutex;
+use std::vec::Vec;
+use std::{eprint, print, thread_local};
+
+use crate::{Entry, Level, Outputter};
+
+// A destination for log lines. Each call is passed one complete line,
+// terminated by a newline.
+pub trait Sink: Send + Sync {
+    fn write_line(&self, line: &str);
+}
+
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn write_line(&self, line: &str) {
+        print!("{}", line);
+    }
+}
+
+pub struct StderrSink;
+
+impl Sink for StderrSink {
+    fn write_line(&self, line: &str) {
+        eprint!("{}", line);
+    }
+}
+
+// Writes lines to anything implementing std::io::Write, e.g. a file or a
+// socket. Write errors are ignored.
+pub struct WriterSink<W>(Mutex<W>);
+
+impl<W: Write + Send> WriterSink<W> {
+    pub fn new(w: W) -> Self {
+        Self(Mutex::new(w))
+    }
+}
+
+impl<W: Write + Send> Sink for WriterSink<W> {
+    fn write_line(&self, line: &str) {
+        if let Ok(mut w) = self.0.lock() {
+            let _ = w.write_all(line.as_bytes());
+        }
+    }
+}
+
+// Keeps lines in memory, without their trailing newline
+#[derive(Default)]
+pub struct MemorySink(Mutex<Vec<String>>);
+
+impl MemorySink {
+    pub const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        match self.0.lock() {
+            Ok(lines) => lines.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+impl Sink for MemorySink {
+    fn write_line(&self, line: &str) {
+        let line = String::from(line.strip_suffix('\n').unwrap_or(line));
+        match self.0.lock() {
+            Ok(mut lines) => lines.push(line),
+            Err(poisoned) => poisoned.into_inner().push(line),
+        }
+    }
+}
+
+// The sink is set at most once, following the log crate's set_logger
+const UNINITIALIZED: usize = 0;
+const INITIALIZING: usize = 1;
+const INITIALIZED: usize = 2;
+
+static STATE: AtomicUsize = AtomicUsize::new(UNINITIALIZED);
+static mut SINK: &dyn Sink = &StdoutSink;
+
+#[derive(Debug)]
+pub struct SetSinkError;
+
+impl fmt::Display for SetSinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sink has already been set")
+    }
+}
+
+impl std::error::Error for SetSinkError {}
+
+// Register the sink every line is written to, stdout until it's set. The
+// sink may only be set once.
+pub fn set_sink(sink: impl Sink + 'static) -> Result<(), SetSinkError> {
+    match STATE.compare_exchange(
+        UNINITIALIZED,
+        INITIALIZING,
+        Ordering::Acquire,
+        Ordering::Relaxed,
+    ) {
+        Ok(_) => {
+            let sink: &'static dyn Sink = Box::leak(Box::new(sink));
+            // Safety: only the thread which moved STATE to INITIALIZING
+            // writes SINK, and it's only read once STATE is INITIALIZED
+            unsafe {
+                SINK = sink;
+            }
+            STATE.store(INITIALIZED, Ordering::Release);
+            Ok(())
+        }
+        Err(_) => Err(SetSinkError),
+    }
+}
+
+fn sink() -> &'static dyn Sink {
+    if STATE.load(Ordering::Acquire) == INITIALIZED {
+        // Safety: SINK is never written once STATE is INITIALIZED
+        unsafe { SINK }
+    } else {
+        &StdoutSink
+    }
+}
+
+struct LineBuf(String);
+
+impl Outputter for LineBuf {
+    fn write_str(&mut self, val: &str) {
+        self.0.push_str(val);
+    }
+
+    fn endline(&mut self) {
+        self.0.push('\n');
+    }
+}
+
+thread_local! {
+    static LINE: RefCell<LineBuf> = const { RefCell::new(LineBuf(String::new())) };
+}
+
+// Log a line to the given sink in place of the registered one
+pub fn log_to<'s>(
+    sink: &dyn Sink,
+    msg: &str,
+    level: Level,
+    entries: impl Iterator<Item = Entry<'s, 's>>,
+) {
+    LINE.with(|line| match line.try_borrow_mut() {
+        Ok(mut line) => {
+            line.0.clear();
+            crate::log(None, &mut *line, msg, level, entries);
+            sink.write_line(&line.0);
+        }
+        // A sink, or an entry value, logging while a line is being written
+        Err(_) => {
+            let mut line = LineBuf(String::new());
+            crate::log(None, &mut line, msg, level, entries);
+            sink.write_line(&line.0);
+        }
+    });
+}
+
+// Log a line to the registered sink
+pub fn sink_log<'s>(msg: &str, level: Level, entries: impl Iterator<Item = Entry<'s, 's>>) {
+    log_to(sink(), msg, level, entries);
+}
+
+static NAMED_SINKS: Mutex<Vec<(&'static str, &'static dyn Sink)>> = Mutex::new(Vec::new());
+
+// Register a sink lines may be routed to by name, e.g. info!(sink = "audit")
+pub fn register_sink(name: &'static str, sink: impl Sink + 'static) {
+    let sink: &'static dyn Sink = Box::leak(Box::new(sink));
+    match NAMED_SINKS.lock() {
+        Ok(mut sinks) => sinks.push((name, sink)),
+        Err(poisoned) => poisoned.into_inner().push((name, sink)),
+    }
+}
+
+// The sink registered under name, falling back to the registered sink
+pub fn named_sink(name: &str) -> &'static dyn Sink {
+    let sinks = match NAMED_SINKS.lock() {
+        Ok(sinks) => sinks,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    match sinks.iter().rev().find(|(n, _)| *n == name) {
+        Some((_, sink)) => *sink,
+        None => sink(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Atom, Value};
+
+    // The lines of sink without their timestamps
+    fn lines(sink: &MemorySink) -> Vec<serde_json::Value> {
+        sink.lines()
+            .iter()
+            .map(|line| {
+                let mut line: serde_json::Value =
+                    serde_json::from_str(line).expect("invalid json line");
+                line.as_object_mut().unwrap().remove("ts");
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn memory_sink() {
+        let sink = MemorySink::new();
+        log_to(
+            &sink,
+            "hello",
+            Level::Info,
+            [Entry {
+                key: "n",
+                value: Value::Atom(Atom::Uint(1)),
+            }]
+            .into_iter(),
+        );
+
+        assert_eq!(
+            lines(&sink),
+            [serde_json::json!({"level": "info", "msg": "hello", "n": 1})]
+        );
+    }
+
+    #[test]
+    fn reentrant_sink() {
+        struct LoggingSink(MemorySink);
+
+        impl Sink for LoggingSink {
+            fn write_line(&self, line: &str) {
+                if line.contains("outer") {
+                    log_to(&self.0, "inner", Level::Info, [].into_iter());
+                }
+                self.0.write_line(line);
+            }
+        }
+
+        let sink = LoggingSink(MemorySink::new());
+        log_to(&sink, "outer", Level::Info, [].into_iter());
+
+        assert_eq!(
+            lines(&sink.0),
+            [
+                serde_json::json!({"level": "info", "msg": "inner"}),
+                serde_json::json!({"level": "info", "msg": "outer"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn named_sinks() {
+        static AUDIT: MemorySink = MemorySink::new();
+
+        struct Audit;
+        impl Sink for Audit {
+            fn write_line(&self, line: &str) {
+                AUDIT.write_line(line);
+            }
+        }
+
+        register_sink("audit", Audit);
+        log_to(
+            named_sink("audit"),
+            "user deleted",
+            Level::Info,
+            [].into_iter(),
+        );
+
+        assert_eq!(
+            lines(&AUDIT),
+            [serde_json::json!({"level": "info", "msg": "user deleted"})]
+        );
+    }
+}
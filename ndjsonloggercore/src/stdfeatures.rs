@@ -0,0 +1,179 @@
+extern crate std;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::error::Error;
+use std::string::{String, ToString};
+use std::sync::Once;
+use std::time::Instant;
+use std::vec::Vec;
+use std::{println, thread_local};
+
+use crate::{Atom, Entry, Level, Value};
+
+#[derive(Default)]
+pub struct StdoutOutputter {
+    buf: String,
+}
+
+impl crate::Outputter for StdoutOutputter {
+    fn write_str(&mut self, val: &str) {
+        self.buf.push_str(val);
+    }
+
+    fn endline(&mut self) {
+        println!("{}", self.buf);
+        self.buf.clear();
+    }
+}
+
+impl StdoutOutputter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+thread_local! {
+    static STDOUT_LOGGER: RefCell<StdoutOutputter> = RefCell::new(StdoutOutputter::new());
+}
+
+pub fn stdout_log<'s>(
+    msg: &str,
+    level: crate::Level,
+    entries: impl Iterator<Item = crate::Entry<'s, 's>>,
+) {
+    STDOUT_LOGGER.with(|out| {
+        crate::log(None, &mut *(out.borrow_mut()), msg, level, entries);
+    });
+}
+
+// LogResultExt logs the error of a Result. E is the error type, or
+// dyn Error for Box<dyn Error> and Box<dyn Error + Send + Sync>, which
+// don't implement Error themselves.
+pub trait LogResultExt<E: ?Sized> {
+    // Log an error line with the error and its source chain if self is an
+    // Err, self is returned unchanged
+    fn log_err(self, msg: &str) -> Self;
+}
+
+impl<T, E: Error> LogResultExt<E> for Result<T, E> {
+    fn log_err(self, msg: &str) -> Self {
+        if let Err(ref e) = self {
+            log_err_chain(msg, e);
+        }
+
+        self
+    }
+}
+
+impl<T> LogResultExt<dyn Error> for Result<T, Box<dyn Error>> {
+    fn log_err(self, msg: &str) -> Self {
+        if let Err(ref e) = self {
+            log_err_chain(msg, e.as_ref());
+        }
+
+        self
+    }
+}
+
+impl<T> LogResultExt<dyn Error + Send + Sync> for Result<T, Box<dyn Error + Send + Sync>> {
+    fn log_err(self, msg: &str) -> Self {
+        if let Err(ref e) = self {
+            log_err_chain(msg, e.as_ref());
+        }
+
+        self
+    }
+}
+
+// log_err_chain logs an error line with the err and its source chain, after
+// the context fields in scope
+fn log_err_chain(msg: &str, e: &dyn Error) {
+    if !crate::level_enabled(Level::Error) {
+        return;
+    }
+
+    let err = e.to_string();
+    let mut chain = Vec::new();
+    let mut source = e.source();
+    while let Some(s) = source {
+        chain.push(s.to_string());
+        source = s.source();
+    }
+
+    let context = crate::context();
+    crate::sink_log(
+        msg,
+        Level::Error,
+        context.entries().chain([
+            Entry {
+                key: "err",
+                value: Value::Atom(Atom::String(&err)),
+            },
+            Entry {
+                key: "err_chain",
+                value: Value::Array(&mut chain.iter().map(|s| Atom::String(s))),
+            },
+        ]),
+    );
+}
+
+static START_INIT: Once = Once::new();
+static mut START: Option<Instant> = None;
+
+// The monotonic time rate limits are measured from, the first time it's read
+fn start() -> Instant {
+    // Safety: START is only written once, inside call_once, and only read
+    // after call_once has returned
+    START_INIT.call_once(|| unsafe { START = Some(Instant::now()) });
+    unsafe { START }.expect("start time is set")
+}
+
+// period_elapsed returns true, at most once per period, when period
+// nanoseconds have passed since it last returned true for last. last holds
+// the time of the last true, 0 for never, and is shared by every thread
+// logging from a call site.
+pub fn period_elapsed(last: &AtomicU64, period: u64) -> bool {
+    // Offset by one so 0 is never a time
+    let now = (start().elapsed().as_nanos() as u64).saturating_add(1);
+    period_elapsed_at(last, period, now)
+}
+
+fn period_elapsed_at(last: &AtomicU64, period: u64, now: u64) -> bool {
+    let prev = last.load(Ordering::Relaxed);
+
+    (prev == 0 || now.saturating_sub(prev) >= period)
+        && last
+            .compare_exchange(prev, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn period_elapsed_() {
+        let last = AtomicU64::new(0);
+        assert!(period_elapsed(&last, 3_600_000_000_000));
+        assert!(!period_elapsed(&last, 3_600_000_000_000));
+
+        let last = AtomicU64::new(0);
+        assert!(period_elapsed(&last, 0));
+        assert!(period_elapsed(&last, 0));
+    }
+
+    #[test]
+    fn period_elapsed_at_() {
+        let last = AtomicU64::new(0);
+        // Never logged, so the period has elapsed
+        assert!(period_elapsed_at(&last, 50, 10));
+        assert_eq!(last.load(Ordering::Relaxed), 10);
+        assert!(!period_elapsed_at(&last, 50, 20));
+        assert!(!period_elapsed_at(&last, 50, 59));
+        assert!(period_elapsed_at(&last, 50, 60));
+        assert!(!period_elapsed_at(&last, 50, 109));
+        // A clock read before the last true doesn't underflow
+        assert!(!period_elapsed_at(&last, 50, 1));
+    }
+}
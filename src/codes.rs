@@ -0,0 +1,144 @@
+use std::env;
+use std::sync::Mutex;
+
+use proc_macro::{Literal, TokenStream, TokenTree};
+
+use crate::compile_error;
+use crate::tokens::{new_punct, new_string};
+
+// Key of the entry holding the event code of the call site
+const CODE_KEY: &str = "code";
+
+// Event codes seen by this compiler process as (crate, code, call site),
+// shared by every thread expanding macros
+static CODES: Mutex<Vec<(String, String, String)>> = Mutex::new(Vec::new());
+
+// code_entry builds the entries group for code = ${code}. A code used by
+// a second call site in the same crate is replaced with a compile_error!
+//
+// The check is best-effort. Proc macros have no crate wide view, so it
+// relies on every call site being expanded by the same process, as rustc
+// does. Hosts which expand call sites piecemeal, e.g. rust-analyzer, may
+// miss a duplicate or, as span identity isn't stable there, report one
+// which rustc doesn't.
+pub fn code_entry(value: Vec<TokenTree>) -> TokenStream {
+    let code = match value.as_slice() {
+        [TokenTree::Literal(lit)] if lit.to_string().starts_with('"') => lit.clone(),
+        _ => panic!("code = expects a string literal, e.g. \"AUTH-0042\""),
+    };
+
+    let mut stream = TokenStream::new();
+    stream.extend([new_string(CODE_KEY), new_punct('=')]);
+    if register_code(&code) {
+        stream.extend([TokenTree::Literal(code)]);
+    } else {
+        stream.extend(compile_error(
+            &format!("event code {} is used by another call site", code),
+            code.span(),
+        ));
+    }
+
+    stream
+}
+
+// register_code returns false if code was already registered by a different
+// call site in the crate being compiled
+fn register_code(code: &Literal) -> bool {
+    let code_span = code.span();
+    let krate = env::var("CARGO_CRATE_NAME").unwrap_or_default();
+
+    let code = code.to_string();
+
+    // A call site may be expanded more than once, e.g. by rust-analyzer, so
+    // it's identified by the span of its code. Span's Debug output is only
+    // stable within a compiler session, which is all the check relies on.
+    let call_site = format!("{:?}", code_span);
+
+    let mut codes = match CODES.lock() {
+        Ok(codes) => codes,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    register(&mut codes, krate, code, call_site)
+}
+
+// register adds (krate, code, call_site) to codes, returning false if code
+// is already registered by a different call site in krate
+fn register(
+    codes: &mut Vec<(String, String, String)>,
+    krate: String,
+    code: String,
+    call_site: String,
+) -> bool {
+    match codes.iter().find(|(k, c, _)| *k == krate && *c == code) {
+        Some((_, _, first_call_site)) => *first_call_site == call_site,
+        None => {
+            codes.push((krate, code, call_site));
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn register(
+        codes: &mut Vec<(String, String, String)>,
+        krate: &str,
+        code: &str,
+        call_site: &str,
+    ) -> bool {
+        super::register(
+            codes,
+            krate.to_string(),
+            code.to_string(),
+            call_site.to_string(),
+        )
+    }
+
+    #[test]
+    fn duplicate_codes() {
+        let mut codes = vec![];
+        assert!(register(
+            &mut codes,
+            "app",
+            "\"AUTH-1\"",
+            "#0 bytes(10..18)"
+        ));
+        // The same call site expanded again
+        assert!(register(
+            &mut codes,
+            "app",
+            "\"AUTH-1\"",
+            "#0 bytes(10..18)"
+        ));
+        // A second call site
+        assert!(!register(
+            &mut codes,
+            "app",
+            "\"AUTH-1\"",
+            "#0 bytes(40..48)"
+        ));
+        assert!(register(
+            &mut codes,
+            "app",
+            "\"AUTH-2\"",
+            "#0 bytes(40..48)"
+        ));
+    }
+
+    #[test]
+    fn codes_per_crate() {
+        let mut codes = vec![];
+        assert!(register(
+            &mut codes,
+            "app",
+            "\"AUTH-1\"",
+            "#0 bytes(10..18)"
+        ));
+        assert!(register(
+            &mut codes,
+            "lib",
+            "\"AUTH-1\"",
+            "#1 bytes(10..18)"
+        ));
+    }
+}
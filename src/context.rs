@@ -0,0 +1,94 @@
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+use crate::entryiter::{EntryIter, EntryLine};
+use crate::logfunc::entries_iter;
+use crate::tokens::{new_group, new_ident, new_path, new_punct};
+
+// fields!({ request_id, user_id: u64 = uid })
+//
+// ndjsonloggercore::Fields::new([ndjsonloggercore::Entry { ... }, ...].into_iter())
+pub fn fields(ts: TokenStream) -> TokenStream {
+    let group = match ts.into_iter().collect::<Vec<TokenTree>>().as_slice() {
+        [TokenTree::Group(grp)] if grp.delimiter() == Delimiter::Brace => grp.stream(),
+        _ => panic!("fields! expects an entries block, e.g. fields!({{ request_id }})"),
+    };
+
+    fields_new(group)
+}
+
+// with_fields!({ request_id }, { ...block... })
+//
+// {
+//     let __ndjsonlogger_fields = ${fields}.enter();
+//     { ...block... }
+// }
+//
+// with_fields!({ request_id }, async move { ... })
+//
+// ndjsonloggercore::WithLogFieldsExt::with_log_fields(async move { ... }, ${fields})
+pub fn with_fields(ts: TokenStream) -> TokenStream {
+    let mut tts = ts.into_iter().collect::<Vec<TokenTree>>();
+    let group = match tts.get(..2) {
+        Some([TokenTree::Group(grp), TokenTree::Punct(comma)])
+            if grp.delimiter() == Delimiter::Brace && comma.as_char() == ',' =>
+        {
+            grp.stream()
+        }
+        _ => panic!("with_fields! expects an entries block and a block"),
+    };
+    let body = tts.split_off(2);
+
+    match body.as_slice() {
+        [TokenTree::Group(block)] if block.delimiter() == Delimiter::Brace => {
+            let mut stream = TokenStream::new();
+            stream.extend([
+                new_ident("let"),
+                new_ident("__ndjsonlogger_fields"),
+                new_punct('='),
+            ]);
+            stream.extend(fields_new(group));
+            stream.extend([
+                new_punct('.'),
+                new_ident("enter"),
+                new_group(Delimiter::Parenthesis, TokenStream::new()),
+                new_punct(';'),
+                TokenTree::Group(block.clone()),
+            ]);
+
+            TokenStream::from(new_group(Delimiter::Brace, stream))
+        }
+        // A thread-local guard can't be held across an await as the task may
+        // move between threads, so async blocks enter the fields on every poll
+        [TokenTree::Ident(ident), .., TokenTree::Group(block)]
+            if ident.to_string() == "async" && block.delimiter() == Delimiter::Brace =>
+        {
+            let mut args = body.into_iter().collect::<TokenStream>();
+            args.extend([new_punct(',')]);
+            args.extend(fields_new(group));
+
+            let mut stream = new_path(&["ndjsonloggercore", "WithLogFieldsExt", "with_log_fields"]);
+            stream.extend([new_group(Delimiter::Parenthesis, args)]);
+            stream
+        }
+        _ => panic!("with_fields! expects a block or an async block after the entries block"),
+    }
+}
+
+// Context fields are kept beyond the scope of the values they were built
+// from, so they're copied into an owned ndjsonloggercore::Fields. Only
+// scalar values, and Options of them, may be copied.
+fn fields_new(group: TokenStream) -> TokenStream {
+    let mut entries = vec![];
+    for entry_line in EntryIter::new(group.into_iter()) {
+        match entry_line {
+            EntryLine::Entry(e) => entries.push(e.into_entry_args()),
+            EntryLine::EntryArray(_) | EntryLine::EntryObject(_) => {
+                panic!("context fields may not be arrays or objects")
+            }
+        }
+    }
+
+    let mut stream = new_path(&["ndjsonloggercore", "Fields", "new"]);
+    stream.extend([new_group(Delimiter::Parenthesis, entries_iter(entries))]);
+    stream
+}
@@ -1,14 +1,27 @@
 use proc_macro::token_stream::IntoIter as TTIter;
-use proc_macro::{Delimiter, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use proc_macro::{Delimiter, Ident, Literal, Spacing, TokenStream, TokenTree};
 
 use std::str::FromStr;
 
 use crate::entryvaluebuilder::{build_entry_value, build_entry_value_array};
-use crate::typefns::EntryType;
+use crate::logfunc::entries_group;
+use crate::tokens::{new_group, new_ident, new_path, new_punct};
+use crate::typefns::{ArrayType, EntryType};
 
 pub enum EntryLine {
     Entry(Entry),
     EntryArray(EntryArray),
+    EntryObject(EntryObject),
+}
+
+impl EntryLine {
+    pub fn into_entry_args(self) -> TokenStream {
+        match self {
+            EntryLine::Entry(e) => e.into_entry_args(),
+            EntryLine::EntryArray(ea) => ea.into_entry_args(),
+            EntryLine::EntryObject(eo) => eo.into_entry_args(),
+        }
+    }
 }
 
 pub struct EntryIter {
@@ -17,26 +30,49 @@ pub struct EntryIter {
 
 impl EntryIter {
     pub fn new(it: TTIter) -> Self {
-        let mut entries = vec![];
-        let mut e = vec![];
-
-        // Split on our commas
-        for tt in it {
-            if let TokenTree::Punct(ref pct) = tt {
-                if pct.as_char() == ',' {
-                    entries.push(e);
-                    e = vec![];
-                    continue;
-                }
-            }
+        let entries = split_entries(it, |tt| match tt {
+            TokenTree::Punct(pct) => Some(pct.as_char()),
+            _ => None,
+        });
 
-            e.push(tt);
-        }
+        Self { entries }
+    }
+}
 
-        entries.push(e);
+// split_entries splits tokens on the commas between entries, punct returns
+// the char of punctuation tokens. Types may hold commas, e.g. Cow<'a, str>,
+// so commas between < > ahead of the = don't split entries.
+fn split_entries<T>(
+    tokens: impl IntoIterator<Item = T>,
+    punct: impl Fn(&T) -> Option<char>,
+) -> Vec<Vec<T>> {
+    let mut entries = vec![];
+    let mut e = vec![];
+    let mut angle_depth = 0;
+    let mut seen_eq = false;
+
+    // Split on our commas
+    for token in tokens {
+        match punct(&token) {
+            Some(',') if seen_eq || angle_depth == 0 => {
+                entries.push(e);
+                e = vec![];
+                angle_depth = 0;
+                seen_eq = false;
+                continue;
+            }
+            Some('<') if !seen_eq => angle_depth += 1,
+            Some('>') if !seen_eq && angle_depth > 0 => angle_depth -= 1,
+            Some('=') => seen_eq = true,
+            _ => (),
+        }
 
-        Self { entries }
+        e.push(token);
     }
+
+    entries.push(e);
+
+    entries
 }
 
 impl Iterator for EntryIter {
@@ -68,7 +104,7 @@ fn parse_entry(mut tts: Vec<TokenTree>) -> EntryLine {
     }
 
     // The first tt is the key - may be either an ident or a literal
-    let key = match tts.get(0).unwrap() {
+    let key = match tts.first().unwrap() {
         TokenTree::Literal(lit) => lit.to_string(),
         TokenTree::Ident(ident) => format!("\"{}\"", ident),
         _ => panic!("invalid tt - expected ident or literal for entry key"),
@@ -77,6 +113,14 @@ fn parse_entry(mut tts: Vec<TokenTree>) -> EntryLine {
     // Second tt may be either an = or a :
     let (entry_type, value_tts) = if let Some(TokenTree::Punct(ref pct)) = tts.get(1) {
         if pct.as_char() == '=' {
+            // A nested object, key = { ... }, unless the group is a block
+            // expression, e.g. key = { if c { "a" } else { "b" } }
+            if let [TokenTree::Group(grp)] = &tts[2..] {
+                if grp.delimiter() == Delimiter::Brace && is_object(&grp.stream()) {
+                    return EntryLine::EntryObject(EntryObject::new(key, grp.stream()));
+                }
+            }
+
             // The value is all tt after the =
             (EntryType::new(), &tts[2..])
         } else if pct.as_char() == ':' {
@@ -93,10 +137,33 @@ fn parse_entry(mut tts: Vec<TokenTree>) -> EntryLine {
     EntryLine::Entry(Entry::new(key, entry_type, value_tts))
 }
 
+// is_object returns true if a braced group is a nested object, i.e. each
+// of its entries is an ident, an [array] or a key followed by = or a type.
+// Anything else is a block expression. A group holding a single ident,
+// { name }, is an object.
+fn is_object(stream: &TokenStream) -> bool {
+    if stream.is_empty() {
+        return true;
+    }
+
+    EntryIter::new(stream.clone().into_iter())
+        .entries
+        .iter()
+        .all(|tts| match tts.as_slice() {
+            [TokenTree::Ident(_)] => true,
+            [TokenTree::Group(grp)] => grp.delimiter() == Delimiter::Bracket,
+            // == and :: are joint, key = and key: are not
+            [TokenTree::Ident(_) | TokenTree::Literal(_), TokenTree::Punct(pct), _, ..] => {
+                matches!(pct.as_char(), '=' | ':') && pct.spacing() == Spacing::Alone
+            }
+            _ => false,
+        })
+}
+
 fn parse_array(stream: TokenStream) -> EntryLine {
     let tts = stream.into_iter().collect::<Vec<TokenTree>>();
     // The first tt is the key - may be either an ident or a literal
-    let key = match tts.get(0).unwrap() {
+    let key = match tts.first().unwrap() {
         TokenTree::Literal(lit) => lit.to_string(),
         TokenTree::Ident(ident) => format!("\"{}\"", ident),
         _ => panic!("invalid tt - expected ident or literal for entry key"),
@@ -106,12 +173,12 @@ fn parse_array(stream: TokenStream) -> EntryLine {
     let (entry_type, value_tts) = if let Some(TokenTree::Punct(ref pct)) = tts.get(1) {
         if pct.as_char() == '=' {
             // The value is all tt after the =
-            (EntryType::new(), &tts[2..])
+            (ArrayType::new(), &tts[2..])
         } else if pct.as_char() == ':' {
             // Parse the type
-            let (entry_type, num_tts) = parse_type(&tts[2..]);
+            let (type_tts, num_tts) = take_type(&tts[2..]);
 
-            (entry_type, &tts[(num_tts + 2)..])
+            (ArrayType::from_type(&type_tts), &tts[(num_tts + 2)..])
         } else {
             panic!("expected : or = following entry key");
         }
@@ -123,6 +190,13 @@ fn parse_array(stream: TokenStream) -> EntryLine {
 }
 
 fn parse_type(tts: &[TokenTree]) -> (EntryType, usize) {
+    let (type_tts, num_tts) = take_type(tts);
+    (EntryType::from_type(&type_tts), num_tts)
+}
+
+// take_type returns the tts of a type up to the =, and the number of tts
+// including the =
+fn take_type(tts: &[TokenTree]) -> (Vec<&TokenTree>, usize) {
     let mut type_tts = vec![];
     let mut num_tts = 0;
     let mut found_eq = false;
@@ -143,7 +217,7 @@ fn parse_type(tts: &[TokenTree]) -> (EntryType, usize) {
         panic!("expected = tt following type declaration");
     }
 
-    (EntryType::from_type(&type_tts), num_tts)
+    (type_tts, num_tts)
 }
 
 fn ident_entry(ident: Ident) -> Entry {
@@ -178,10 +252,10 @@ pub struct EntryArray {
 }
 
 impl EntryArray {
-    fn new(key: String, entry_type: EntryType, value_tts: &[TokenTree]) -> Self {
+    fn new(key: String, array_type: ArrayType, value_tts: &[TokenTree]) -> Self {
         Self {
             key,
-            value_group: build_entry_value_array(entry_type, value_tts),
+            value_group: build_entry_value_array(&array_type, value_tts),
         }
     }
 
@@ -190,17 +264,106 @@ impl EntryArray {
     }
 }
 
-fn new_entry_args(key: &str, value: TokenStream) -> TokenStream {
+// EntryObject is a nested object, its entries parsed as those of the line
+// ndjsonloggercore::Value::Object(&mut [ndjsonloggercore::Entry { ... }, ...])
+pub struct EntryObject {
+    key: String,
+    value_group: TokenStream,
+}
+
+impl EntryObject {
+    fn new(key: String, stream: TokenStream) -> Self {
+        let entries = if stream.is_empty() {
+            vec![]
+        } else {
+            EntryIter::new(stream.into_iter())
+                .map(EntryLine::into_entry_args)
+                .collect()
+        };
+
+        let mut object = TokenStream::new();
+        object.extend([
+            new_punct('&'),
+            new_ident("mut"),
+            new_group(Delimiter::Bracket, entries_group(entries)),
+        ]);
+
+        let mut value_group = new_path(&["ndjsonloggercore", "Value", "Object"]);
+        value_group.extend([new_group(Delimiter::Parenthesis, object)]);
+
+        Self { key, value_group }
+    }
+
+    pub fn into_entry_args(self) -> TokenStream {
+        new_entry_args(&self.key, self.value_group)
+    }
+}
+
+pub fn new_entry_args(key: &str, value: TokenStream) -> TokenStream {
     let mut entry_args = TokenStream::new();
     entry_args.extend([
-        TokenTree::Ident(Ident::new("key", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
+        new_ident("key"),
+        new_punct(':'),
         TokenTree::Literal(Literal::from_str(key).expect("invalid entry key")),
-        TokenTree::Punct(Punct::new(',', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("value", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
+        new_punct(','),
+        new_ident("value"),
+        new_punct(':'),
     ]);
     entry_args.extend(value);
 
     entry_args
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // split splits entries given as space separated tokens
+    fn split(entries: &str) -> Vec<String> {
+        let punct = |token: &&str| match token.chars().collect::<Vec<char>>().as_slice() {
+            [c] if !c.is_alphanumeric() && *c != '_' => Some(*c),
+            _ => None,
+        };
+
+        split_entries(entries.split_whitespace(), punct)
+            .iter()
+            .map(|e| e.join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn split_on_commas() {
+        assert_eq!(
+            split("a , b = c , d : u32 = e"),
+            ["a", "b = c", "d : u32 = e"]
+        );
+        assert_eq!(split("a"), ["a"]);
+        // A trailing comma leaves an empty entry
+        assert_eq!(split("a ,"), ["a", ""]);
+    }
+
+    #[test]
+    fn types_with_commas() {
+        assert_eq!(
+            split("name : Cow < ' a , str > = name , id : u64 = id"),
+            ["name : Cow < ' a , str > = name", "id : u64 = id"]
+        );
+        assert_eq!(
+            split("nick : Option < Cow < ' static , str > > = nick , b"),
+            ["nick : Option < Cow < ' static , str > > = nick", "b"]
+        );
+    }
+
+    #[test]
+    fn comparisons_in_values() {
+        // < and > after the = are operators, not brackets
+        assert_eq!(
+            split("slow : bool = elapsed > limit , fast : bool = elapsed < limit , c"),
+            [
+                "slow : bool = elapsed > limit",
+                "fast : bool = elapsed < limit",
+                "c"
+            ]
+        );
+    }
+}
@@ -1,18 +1,21 @@
-use proc_macro::{Delimiter, Group, Ident, Punct, Spacing, Span, TokenStream, TokenTree};
+use proc_macro::{Delimiter, TokenStream, TokenTree};
 
-use crate::typefns::EntryType;
+use crate::entryiter::new_entry_args;
+use crate::logfunc::entries_group;
+use crate::tokens::{new_group, new_ident, new_op, new_path, new_punct};
+use crate::typefns::{ArrayType, ElementType, EntryType};
 
 pub fn build_entry_value(entry_type: EntryType, value_tts: &[TokenTree]) -> TokenStream {
     match (entry_type.is_opt(), entry_type.is_str()) {
         (true, true) => {
-            // Option<&str>
-            entry_opt_str(value_tts)
+            // Option<&str> or an Option of an owned string type
+            entry_opt_str(entry_type, value_tts)
         }
         (true, false) => {
             let (atom_type, primative_cast) = entry_type.atom_type();
             entry_opt_primative(atom_type, primative_cast, value_tts)
         }
-        (false, true) => entry_str(value_tts),
+        (false, true) => entry_str(entry_type, value_tts),
         (false, false) => {
             let (atom_type, primative_cast) = entry_type.atom_type();
             entry_primative(atom_type, primative_cast, value_tts)
@@ -20,42 +23,56 @@ pub fn build_entry_value(entry_type: EntryType, value_tts: &[TokenTree]) -> Toke
     }
 }
 
-pub fn build_entry_value_array(entry_type: EntryType, value_tts: &[TokenTree]) -> TokenStream {
-    match (entry_type.is_opt(), entry_type.is_str()) {
-        (true, true) => {
-            panic!("Option<_> not supported inside array");
+pub fn build_entry_value_array(array_type: &ArrayType, value_tts: &[TokenTree]) -> TokenStream {
+    match array_type.element() {
+        ElementType::Atom(entry_type) => {
+            let map_fn = element_mapfn(*entry_type);
+            if array_type.is_opt() {
+                entry_opt_array(map_fn, value_tts)
+            } else {
+                entry_array(map_fn, value_tts)
+            }
         }
-        (true, false) => {
-            panic!("Option<_> not supported inside array");
-        }
-        (false, true) => entry_str_array(value_tts),
-        (false, false) => {
-            let (atom_type, primative_cast) = entry_type.atom_type();
-            entry_primative_array(atom_type, primative_cast, value_tts)
+        element => {
+            let visit = element_visit(element);
+            if array_type.is_opt() {
+                entry_opt_nested(visit, value_tts)
+            } else {
+                entry_nested(visit, value_tts)
+            }
         }
     }
 }
 
-// entry_opt_str builds the Value an Option<&str>
-// ndjsonloggercore::Value::Optatom({$value}.map(|s| ndjsonloggercore::Atom::String(s))
-fn entry_opt_str(value_tts: &[TokenTree]) -> TokenStream {
+// entry_opt_str builds the Value an Option of a string type
+// Option<&str>:
+// ndjsonloggercore::Value::Optatom(${value}.map(|s| ndjsonloggercore::Atom::String(s)))
+// Option of an owned string type:
+// ndjsonloggercore::Value::Optatom(
+//    ${value}.as_ref().map(|s| ndjsonloggercore::Atom::String(std::convert::AsRef::<str>::as_ref(s)))
+// )
+fn entry_opt_str(entry_type: EntryType, value_tts: &[TokenTree]) -> TokenStream {
     let mut stream = new_ndjsoncore_value("Optatom");
 
     let mut map_fn = new_mapfn("s", "String");
-    map_fn.extend([new_single_ident_group("s", false, None)]);
+    map_fn.extend([new_str_group(entry_type, "s")]);
 
     let mut str_option = TokenStream::new();
     str_option.extend(value_tts.iter().map(|tt| tt.to_owned()));
+    if entry_type.is_owned_str() {
+        str_option.extend([
+            new_punct('.'),
+            new_ident("as_ref"),
+            new_group(Delimiter::Parenthesis, TokenStream::new()),
+        ]);
+    }
     str_option.extend([
-        TokenTree::Punct(Punct::new('.', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("map", Span::call_site())),
-        TokenTree::Group(Group::new(Delimiter::Parenthesis, map_fn)),
+        new_punct('.'),
+        new_ident("map"),
+        new_group(Delimiter::Parenthesis, map_fn),
     ]);
 
-    stream.extend([TokenTree::Group(Group::new(
-        Delimiter::Parenthesis,
-        str_option,
-    ))]);
+    stream.extend([new_group(Delimiter::Parenthesis, str_option)]);
 
     stream
 }
@@ -76,28 +93,35 @@ fn entry_opt_primative(
     let mut option = TokenStream::new();
     option.extend(value_tts.iter().map(|tt| tt.to_owned()));
     option.extend([
-        TokenTree::Punct(Punct::new('.', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("map", Span::call_site())),
-        TokenTree::Group(Group::new(Delimiter::Parenthesis, map_fn)),
+        new_punct('.'),
+        new_ident("map"),
+        new_group(Delimiter::Parenthesis, map_fn),
     ]);
 
-    stream.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, option))]);
+    stream.extend([new_group(Delimiter::Parenthesis, option)]);
 
     stream
 }
 
-// entry_str builds the Value for &str
-// ndjsonloggercore::Value::Atom(ndjsonloggercore::Atom::String(${value})
-fn entry_str(value_tts: &[TokenTree]) -> TokenStream {
+// entry_str builds the Value for a string type. &str and untyped values are
+// passed as is, so any value which derefs to a str may be given:
+// ndjsonloggercore::Value::Atom(ndjsonloggercore::Atom::String(${value}))
+// Owned and shared string types are borrowed as a &str:
+// ndjsonloggercore::Value::Atom(ndjsonloggercore::Atom::String(std::convert::AsRef::<str>::as_ref(&(${value})))
+fn entry_str(entry_type: EntryType, value_tts: &[TokenTree]) -> TokenStream {
     let mut stream = new_ndjsoncore_value("Atom");
 
-    let mut inner = TokenStream::new();
-    inner.extend(value_tts.iter().map(|s| s.to_owned()));
-
     let mut atom = new_ndjsoncore_atom("String");
-    atom.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, inner))]);
+    if entry_type.is_owned_str() {
+        atom.extend([new_as_str_group(value_tts, true)]);
+    } else {
+        atom.extend([new_group(
+            Delimiter::Parenthesis,
+            value_tts.iter().map(respan_block).collect(),
+        )]);
+    }
 
-    stream.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, atom))]);
+    stream.extend([new_group(Delimiter::Parenthesis, atom)]);
     stream
 }
 
@@ -114,144 +138,397 @@ fn entry_primative(
     inner.extend(value_tts.iter().map(|p| p.to_owned()));
 
     if let Some(primative_cast) = primative_cast {
-        inner.extend([
-            TokenTree::Ident(Ident::new("as", Span::call_site())),
-            TokenTree::Ident(Ident::new(primative_cast, Span::call_site())),
-        ]);
+        inner.extend([new_ident("as"), new_ident(primative_cast)]);
     }
 
     let mut atom = new_ndjsoncore_atom(atom_type);
-    atom.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, inner))]);
+    atom.extend([new_group(Delimiter::Parenthesis, inner)]);
 
-    stream.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, atom))]);
+    stream.extend([new_group(Delimiter::Parenthesis, atom)]);
     stream
 }
 
-// entry_str_array builds the Value for a [&str]
-// ndjsonloggercore::Value::Array(
-//    &mut ${value}.iter().map(|s| ndjosnloggercore::Atom::String(s))
-// )
-fn entry_str_array(value_tts: &[TokenTree]) -> TokenStream {
+// entry_array builds the Value for a [${element}]
+// ndjsonloggercore::Value::Array(&mut ${value}.iter().map(${map_fn}))
+fn entry_array(map_fn: TokenStream, value_tts: &[TokenTree]) -> TokenStream {
     let mut stream = new_ndjsoncore_value("Array");
 
-    let mut map_fn = new_mapfn("s", "String");
-    map_fn.extend([new_single_ident_group("s", false, None)]);
+    let mut iter = new_iter(value_tts);
+    iter.extend([new_group(Delimiter::Parenthesis, map_fn)]);
+
+    stream.extend([new_group(Delimiter::Parenthesis, iter)]);
+    stream
+}
+
+// entry_opt_array builds the Value for an Option<&[${element}]>
+// ndjsonloggercore::Value::Optarray(
+//    ${value}
+//        .as_ref()
+//        .map(|__ndjsonlogger_v| __ndjsonlogger_v.iter().map(${map_fn}))
+//        .as_mut()
+//        .map(|__ndjsonlogger_i| __ndjsonlogger_i as &mut dyn Iterator<Item = ndjsonloggercore::Atom>)
+// )
+fn entry_opt_array(map_fn: TokenStream, value_tts: &[TokenTree]) -> TokenStream {
+    let mut stream = new_ndjsoncore_value("Optarray");
+
+    let v = new_ident("__ndjsonlogger_v");
+    let i = new_ident("__ndjsonlogger_i");
+
+    let mut iter_fn = TokenStream::new();
+    iter_fn.extend([
+        new_punct('|'),
+        v.clone(),
+        new_punct('|'),
+        v,
+        new_punct('.'),
+        new_ident("iter"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct('.'),
+        new_ident("map"),
+        new_group(Delimiter::Parenthesis, map_fn),
+    ]);
 
-    let mut iter = new_iter(value_tts, false);
-    iter.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, map_fn))]);
+    let mut dyn_fn = TokenStream::new();
+    dyn_fn.extend([
+        new_punct('|'),
+        i.clone(),
+        new_punct('|'),
+        i,
+        new_ident("as"),
+        new_punct('&'),
+        new_ident("mut"),
+        new_ident("dyn"),
+        new_ident("Iterator"),
+        new_punct('<'),
+        new_ident("Item"),
+        new_punct('='),
+    ]);
+    dyn_fn.extend(new_path(&["ndjsonloggercore", "Atom"]));
+    dyn_fn.extend([new_punct('>')]);
 
-    stream.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, iter))]);
+    let mut option = TokenStream::new();
+    option.extend(value_tts.iter().map(|tt| tt.to_owned()));
+    option.extend([
+        new_punct('.'),
+        new_ident("as_ref"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct('.'),
+        new_ident("map"),
+        new_group(Delimiter::Parenthesis, iter_fn),
+        new_punct('.'),
+        new_ident("as_mut"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct('.'),
+        new_ident("map"),
+        new_group(Delimiter::Parenthesis, dyn_fn),
+    ]);
+
+    stream.extend([new_group(Delimiter::Parenthesis, option)]);
     stream
 }
 
-// entry_primative_array builds the Value for a [primative]
-// ndjsonloggercore::Value::Array(
-//    &mut ${value}.iter().map(|p| ndjosnloggercore::Atom::String(*p as ${primative_cast}))
+// entry_nested builds the Value for an array of arrays or objects
+// ndjsonloggercore::Value::Nested(
+//    &mut |__ndjsonlogger_visit: &mut dyn FnMut(ndjsonloggercore::Value)| {
+//        for __ndjsonlogger_e in ${value}.iter() { ${visit} }
+//    }
 // )
-fn entry_primative_array(
-    atom_type: &str,
-    primative_cast: Option<&str>,
-    value_tts: &[TokenTree],
-) -> TokenStream {
-    let mut stream = new_ndjsoncore_value("Array");
+fn entry_nested(visit: TokenStream, value_tts: &[TokenTree]) -> TokenStream {
+    let mut stream = new_ndjsoncore_value("Nested");
 
-    let mut map_fn = new_mapfn("p", atom_type);
-    map_fn.extend([new_single_ident_group("p", true, primative_cast)]);
+    let mut nested = TokenStream::new();
+    nested.extend([new_punct('&'), new_ident("mut")]);
+    nested.extend(new_visit_fn(visit, value_tts));
+
+    stream.extend([new_group(Delimiter::Parenthesis, nested)]);
+    stream
+}
+
+// entry_opt_nested builds the Value for an Option<&[${element}]> of arrays or
+// objects
+// ndjsonloggercore::Value::Optnested(
+//    ${value}
+//        .as_ref()
+//        .map(|__ndjsonlogger_v| move |__ndjsonlogger_visit: ..| { .. __ndjsonlogger_v.iter() .. })
+//        .as_mut()
+//        .map(|__ndjsonlogger_f| __ndjsonlogger_f as &mut dyn FnMut(&mut dyn FnMut(ndjsonloggercore::Value)))
+// )
+fn entry_opt_nested(visit: TokenStream, value_tts: &[TokenTree]) -> TokenStream {
+    let mut stream = new_ndjsoncore_value("Optnested");
+
+    let v = new_ident("__ndjsonlogger_v");
+    let f = new_ident("__ndjsonlogger_f");
+
+    let mut visit_fn = TokenStream::new();
+    visit_fn.extend([new_punct('|'), v.clone(), new_punct('|'), new_ident("move")]);
+    visit_fn.extend(new_visit_fn(visit, &[v]));
+
+    let mut dyn_fn = TokenStream::new();
+    dyn_fn.extend([
+        new_punct('|'),
+        f.clone(),
+        new_punct('|'),
+        f,
+        new_ident("as"),
+    ]);
+    dyn_fn.extend(new_dyn_fn_mut(new_dyn_fn_mut(new_ndjsoncore_type("Value"))));
 
-    let mut iter = new_iter(value_tts, false);
-    iter.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, map_fn))]);
+    let mut option = TokenStream::new();
+    option.extend(value_tts.iter().map(|tt| tt.to_owned()));
+    option.extend([
+        new_punct('.'),
+        new_ident("as_ref"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct('.'),
+        new_ident("map"),
+        new_group(Delimiter::Parenthesis, visit_fn),
+        new_punct('.'),
+        new_ident("as_mut"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct('.'),
+        new_ident("map"),
+        new_group(Delimiter::Parenthesis, dyn_fn),
+    ]);
 
-    stream.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, iter))]);
+    stream.extend([new_group(Delimiter::Parenthesis, option)]);
     stream
 }
 
-fn new_ndjsoncore_value(value_variant: &str) -> TokenStream {
+// |__ndjsonlogger_visit: &mut dyn FnMut(ndjsonloggercore::Value)| {
+//     for __ndjsonlogger_e in ${value}.iter() { ${visit} }
+// }
+fn new_visit_fn(visit: TokenStream, value_tts: &[TokenTree]) -> TokenStream {
     let mut stream = TokenStream::new();
     stream.extend([
-        TokenTree::Ident(Ident::new("ndjsonloggercore", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("Value", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-        TokenTree::Ident(Ident::new(value_variant, Span::call_site())),
+        new_punct('|'),
+        new_ident("__ndjsonlogger_visit"),
+        new_punct(':'),
+    ]);
+    stream.extend(new_dyn_fn_mut(new_ndjsoncore_type("Value")));
+    stream.extend([new_punct('|')]);
+
+    let mut for_loop = TokenStream::new();
+    for_loop.extend([
+        new_ident("for"),
+        new_ident("__ndjsonlogger_e"),
+        new_ident("in"),
+    ]);
+    for_loop.extend(value_tts.iter().map(|tt| tt.to_owned()));
+    for_loop.extend([
+        new_punct('.'),
+        new_ident("iter"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_group(Delimiter::Brace, visit),
     ]);
+
+    stream.extend([new_group(Delimiter::Brace, for_loop)]);
     stream
 }
 
-fn new_ndjsoncore_atom(variant: &str) -> TokenStream {
+// element_visit builds the visit of an element of an array of arrays or
+// objects
+// __ndjsonlogger_visit(${element value of __ndjsonlogger_e});
+fn element_visit(element: &ElementType) -> TokenStream {
+    let e = new_ident("__ndjsonlogger_e");
+
     let mut stream = TokenStream::new();
     stream.extend([
-        TokenTree::Ident(Ident::new("ndjsonloggercore", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("Atom", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-        TokenTree::Ident(Ident::new(variant, Span::call_site())),
+        new_ident("__ndjsonlogger_visit"),
+        new_group(Delimiter::Parenthesis, element_value(element, &[e])),
+        new_punct(';'),
     ]);
     stream
 }
 
+// element_value builds the Value of an element, or of a field of an object
+// element
+// Object: ndjsonloggercore::Value::Object(&mut [
+//             ndjsonloggercore::Entry { key: "${field}", value: ${element value of ${value}.${field}} },
+//             ...
+//         ])
+fn element_value(element: &ElementType, value_tts: &[TokenTree]) -> TokenStream {
+    match element {
+        ElementType::Atom(entry_type) => build_entry_value(*entry_type, value_tts),
+        ElementType::Array(array_type) => build_entry_value_array(array_type, value_tts),
+        ElementType::Object(fields) => {
+            let entries = fields
+                .iter()
+                .map(|(name, field)| {
+                    let mut field_tts = value_tts.to_vec();
+                    field_tts.extend([new_punct('.'), new_ident(name)]);
+
+                    new_entry_args(&format!("\"{}\"", name), element_value(field, &field_tts))
+                })
+                .collect();
+
+            let mut object = TokenStream::new();
+            object.extend([
+                new_punct('&'),
+                new_ident("mut"),
+                new_group(Delimiter::Bracket, entries_group(entries)),
+            ]);
+
+            let mut stream = new_ndjsoncore_value("Object");
+            stream.extend([new_group(Delimiter::Parenthesis, object)]);
+            stream
+        }
+    }
+}
+
+// element_mapfn builds the closure mapping an array element to an Atom
+// &str:              |s| ndjsonloggercore::Atom::String(s)
+// owned string type: |s| ndjsonloggercore::Atom::String(std::convert::AsRef::<str>::as_ref(s))
+// prim:              |p| ndjsonloggercore::Atom::${atom_type}(*p as ${primative_cast})
+// Option<&str|prim>: |p| match p {
+//                        Some(p) => ndjsonloggercore::Atom::${atom_type}(..),
+//                        None => ndjsonloggercore::Atom::Null,
+//                    }
+fn element_mapfn(entry_type: EntryType) -> TokenStream {
+    let (atom_type, primative_cast) = entry_type.atom_type();
+
+    if !entry_type.is_opt() {
+        return if entry_type.is_str() {
+            let mut map_fn = new_mapfn("s", "String");
+            map_fn.extend([new_str_group(entry_type, "s")]);
+            map_fn
+        } else {
+            let mut map_fn = new_mapfn("p", atom_type);
+            map_fn.extend([new_single_ident_group("p", true, primative_cast)]);
+            map_fn
+        };
+    }
+
+    let mut some_atom = new_ndjsoncore_atom(atom_type);
+    if entry_type.is_str() {
+        some_atom.extend([new_str_group(entry_type, "p")]);
+    } else {
+        some_atom.extend([new_single_ident_group("p", true, primative_cast)]);
+    }
+
+    let mut arms = TokenStream::new();
+    arms.extend([new_ident("Some"), new_single_ident_group("p", false, None)]);
+    arms.extend(new_op("=>"));
+    arms.extend(some_atom);
+    arms.extend([new_punct(','), new_ident("None")]);
+    arms.extend(new_op("=>"));
+    arms.extend(new_ndjsoncore_atom("Null"));
+
+    let mut map_fn = TokenStream::new();
+    map_fn.extend([
+        new_punct('|'),
+        new_ident("p"),
+        new_punct('|'),
+        new_ident("match"),
+        new_ident("p"),
+        new_group(Delimiter::Brace, arms),
+    ]);
+    map_fn
+}
+
+fn new_ndjsoncore_value(value_variant: &str) -> TokenStream {
+    new_path(&["ndjsonloggercore", "Value", value_variant])
+}
+
+// ndjsonloggercore::${name}
+fn new_ndjsoncore_type(name: &str) -> TokenStream {
+    new_path(&["ndjsonloggercore", name])
+}
+
+// &mut dyn FnMut(${arg})
+fn new_dyn_fn_mut(arg: TokenStream) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_punct('&'),
+        new_ident("mut"),
+        new_ident("dyn"),
+        new_ident("FnMut"),
+        new_group(Delimiter::Parenthesis, arg),
+    ]);
+    stream
+}
+
+fn new_ndjsoncore_atom(variant: &str) -> TokenStream {
+    new_path(&["ndjsonloggercore", "Atom", variant])
+}
+
 // ($ident) or (*$ident as $primative_cast)
 fn new_single_ident_group(ident: &str, deref: bool, primative_cast: Option<&str>) -> TokenTree {
     let mut ident_group = TokenStream::new();
     if deref {
-        ident_group.extend([TokenTree::Punct(Punct::new('*', Spacing::Alone))]);
+        ident_group.extend([new_punct('*')]);
     }
-    ident_group.extend([TokenTree::Ident(Ident::new(ident, Span::call_site()))]);
+    ident_group.extend([new_ident(ident)]);
 
     if let Some(primative_cast) = primative_cast {
-        ident_group.extend([
-            TokenTree::Ident(Ident::new("as", Span::call_site())),
-            TokenTree::Ident(Ident::new(primative_cast, Span::call_site())),
+        ident_group.extend([new_ident("as"), new_ident(primative_cast)]);
+    }
+
+    new_group(Delimiter::Parenthesis, ident_group)
+}
+
+// (std::convert::AsRef::<str>::as_ref(${value})) or with &(${value}), borrowing
+// any of the string types as a &str
+fn new_as_str_group(value_tts: &[TokenTree], borrow: bool) -> TokenTree {
+    let mut stream = new_path(&["std", "convert", "AsRef"]);
+    stream.extend(new_op("::"));
+    stream.extend([new_punct('<'), new_ident("str"), new_punct('>')]);
+    stream.extend(new_op("::"));
+    stream.extend([new_ident("as_ref")]);
+
+    let mut value = TokenStream::new();
+    if borrow {
+        value.extend([
+            new_punct('&'),
+            new_group(Delimiter::Parenthesis, value_tts.iter().cloned().collect()),
         ]);
+    } else {
+        value.extend(value_tts.iter().cloned());
+    }
+    stream.extend([new_group(Delimiter::Parenthesis, value)]);
+
+    new_group(Delimiter::Parenthesis, stream)
+}
+
+// respan_block gives a block expression value the call site span, so the
+// unused_braces lint doesn't fire on the user's braces once the block is an
+// argument of ndjsonloggercore::Atom::String
+fn respan_block(tt: &TokenTree) -> TokenTree {
+    match tt {
+        TokenTree::Group(grp) if grp.delimiter() == Delimiter::Brace => {
+            new_group(Delimiter::Brace, grp.stream())
+        }
+        tt => tt.clone(),
     }
+}
 
-    TokenTree::Group(Group::new(Delimiter::Parenthesis, ident_group))
+// (${ident}) for &str, or (std::convert::AsRef::<str>::as_ref(${ident})) for
+// the owned and shared string types
+fn new_str_group(entry_type: EntryType, ident: &str) -> TokenTree {
+    if entry_type.is_owned_str() {
+        new_as_str_group(&[new_ident(ident)], false)
+    } else {
+        new_single_ident_group(ident, false, None)
+    }
 }
 
 fn new_mapfn(ident: &str, atom_type: &str) -> TokenStream {
     let mut map_fn = TokenStream::new();
-    map_fn.extend([
-        TokenTree::Punct(Punct::new('|', Spacing::Alone)),
-        TokenTree::Ident(Ident::new(ident, Span::call_site())),
-        TokenTree::Punct(Punct::new('|', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("ndjsonloggercore", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("Atom", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-        TokenTree::Ident(Ident::new(atom_type, Span::call_site())),
-    ]);
+    map_fn.extend([new_punct('|'), new_ident(ident), new_punct('|')]);
+    map_fn.extend(new_ndjsoncore_atom(atom_type));
     map_fn
 }
 
-fn new_iter(tts: &[TokenTree], flatten: bool) -> TokenStream {
+fn new_iter(tts: &[TokenTree]) -> TokenStream {
     let mut stream = TokenStream::new();
-    stream.extend([
-        TokenTree::Punct(Punct::new('&', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("mut", Span::call_site())),
-    ]);
+    stream.extend([new_punct('&'), new_ident("mut")]);
 
     stream.extend(tts.iter().map(|tt| tt.to_owned()));
     stream.extend([
-        TokenTree::Punct(Punct::new('.', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("iter", Span::call_site())),
-        TokenTree::Group(Group::new(Delimiter::Parenthesis, TokenStream::new())),
-    ]);
-    if flatten {
-        stream.extend([
-            TokenTree::Punct(Punct::new('.', Spacing::Alone)),
-            TokenTree::Ident(Ident::new("flatten", Span::call_site())),
-            TokenTree::Group(Group::new(Delimiter::Parenthesis, TokenStream::new())),
-        ]);
-    }
-    stream.extend([
-        TokenTree::Punct(Punct::new('.', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("map", Span::call_site())),
+        new_punct('.'),
+        new_ident("iter"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
     ]);
+    stream.extend([new_punct('.'), new_ident("map")]);
 
     stream
 }
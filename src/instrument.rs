@@ -0,0 +1,390 @@
+use proc_macro::{Delimiter, Ident, Literal, TokenStream, TokenTree};
+
+use crate::levels::level_name;
+use crate::timed::{bind_elapsed_us, elapsed_entry, start_timer};
+use crate::tokens::{new_group, new_ident, new_op, new_path, new_punct, new_string};
+use crate::{add_log_entries_from_group, build_log_level, log_function, maxlevel};
+
+// Keys of the entries added to the enter and exit lines
+const EVENT_KEY: &str = "event";
+const RESULT_KEY: &str = "result";
+
+const RET_BINDING: &str = "__ndjsonlogger_ret";
+
+// #[instrument(level = "debug", fields(user_id, name))]
+//
+// fn ${name}(${params}) -> ${ret} {
+//     let __ndjsonlogger_start = std::time::Instant::now();
+//     ${enter log function}
+//     let __ndjsonlogger_ret = (move || -> ${ret} { ${body} })();
+//     let __ndjsonlogger_elapsed_us = __ndjsonlogger_start.elapsed().as_micros() as u64;
+//     ${exit log function}
+//     __ndjsonlogger_ret
+// }
+//
+// async fns await an async move block in place of calling a closure
+pub fn instrument(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let (level, fields) = parse_attr(attr);
+    if !maxlevel::level_enabled(level) {
+        return item;
+    }
+
+    let mut tts = item.into_iter().collect::<Vec<TokenTree>>();
+    let body = match tts.pop() {
+        Some(TokenTree::Group(grp)) if grp.delimiter() == Delimiter::Brace => grp,
+        _ => panic!("#[instrument] may only be used on a fn with a body"),
+    };
+    let sig = Signature::parse(&tts);
+
+    let mut stream = start_timer();
+
+    // enter line
+    let mut enter = TokenStream::new();
+    enter.extend([new_string(EVENT_KEY), new_punct('='), new_string("enter")]);
+    for field in fields {
+        enter.extend([new_punct(',')]);
+        enter.extend(sig.field_entry(&field));
+    }
+    stream.extend(log_line(level, &sig.name, enter));
+
+    // body, the return type is given where it can be named so return, ?
+    // and the tail of the body coerce as they would in the original fn
+    let ret = sig.ret.as_ref().filter(|ret| {
+        !ret.iter()
+            .any(|tt| matches!(tt, TokenTree::Ident(ident) if ident.to_string() == "impl"))
+    });
+    stream.extend(new_allow(&["clippy", "redundant_closure_call"]));
+    stream.extend([new_ident("let"), new_ident(RET_BINDING), new_punct('=')]);
+    if sig.is_async {
+        // async move {
+        //     #[allow(unreachable_code)]
+        //     if false { let __ndjsonlogger_ret: ${ret} = loop {}; return __ndjsonlogger_ret; }
+        //     ${body}
+        // }.await;
+        let mut block = TokenStream::new();
+        if let Some(ret) = ret {
+            let mut fake_return = TokenStream::new();
+            fake_return.extend([new_ident("let"), new_ident(RET_BINDING), new_punct(':')]);
+            fake_return.extend(ret.iter().cloned());
+            fake_return.extend([
+                new_punct('='),
+                new_ident("loop"),
+                new_group(Delimiter::Brace, TokenStream::new()),
+                new_punct(';'),
+                new_ident("return"),
+                new_ident(RET_BINDING),
+                new_punct(';'),
+            ]);
+
+            block.extend(new_allow(&["unreachable_code"]));
+            block.extend([
+                new_ident("if"),
+                new_ident("false"),
+                new_group(Delimiter::Brace, fake_return),
+            ]);
+        }
+        block.extend(body.stream());
+
+        stream.extend([
+            new_ident("async"),
+            new_ident("move"),
+            new_group(Delimiter::Brace, block),
+            new_punct('.'),
+            new_ident("await"),
+            new_punct(';'),
+        ]);
+    } else {
+        // (move || -> ${ret} { ${body} })();
+        let mut closure = TokenStream::new();
+        closure.extend([new_ident("move")]);
+        closure.extend(new_op("||"));
+        if let Some(ret) = ret {
+            closure.extend(new_op("->"));
+            closure.extend(ret.iter().cloned());
+        }
+        closure.extend([TokenTree::Group(body)]);
+        stream.extend([
+            new_group(Delimiter::Parenthesis, closure),
+            new_group(Delimiter::Parenthesis, TokenStream::new()),
+            new_punct(';'),
+        ]);
+    }
+
+    // exit line
+    stream.extend(bind_elapsed_us());
+    let mut exit = TokenStream::new();
+    exit.extend([
+        new_string(EVENT_KEY),
+        new_punct('='),
+        new_string("exit"),
+        new_punct(','),
+    ]);
+    exit.extend(elapsed_entry());
+    if sig.returns_result() {
+        // "result" = if __ndjsonlogger_ret.is_ok() { "ok" } else { "err" }
+        exit.extend([
+            new_punct(','),
+            new_string(RESULT_KEY),
+            new_punct('='),
+            new_ident("if"),
+            new_ident(RET_BINDING),
+            new_punct('.'),
+            new_ident("is_ok"),
+            new_group(Delimiter::Parenthesis, TokenStream::new()),
+            new_group(Delimiter::Brace, new_string("ok").into()),
+            new_ident("else"),
+            new_group(Delimiter::Brace, new_string("err").into()),
+        ]);
+    }
+    stream.extend(log_line(level, &sig.name, exit));
+
+    stream.extend([new_ident(RET_BINDING)]);
+
+    let mut out = tts.into_iter().collect::<TokenStream>();
+    out.extend([new_group(Delimiter::Brace, stream)]);
+    out
+}
+
+// #[allow(${lint})]
+fn new_allow(lint: &[&str]) -> TokenStream {
+    let mut attr = TokenStream::new();
+    attr.extend([
+        new_ident("allow"),
+        new_group(Delimiter::Parenthesis, new_path(lint)),
+    ]);
+
+    let mut stream = TokenStream::new();
+    stream.extend([new_punct('#'), new_group(Delimiter::Bracket, attr)]);
+    stream
+}
+
+// ${log function}; with the fn name as the message
+fn log_line(level: &str, name: &str, entries: TokenStream) -> TokenStream {
+    let mut log_function = log_function(
+        TokenStream::from(TokenTree::Literal(Literal::string(name))).into_iter(),
+        build_log_level(level),
+    );
+    add_log_entries_from_group(entries, &mut log_function);
+
+    let mut stream = log_function.into_token_stream();
+    stream.extend([new_punct(';')]);
+    stream
+}
+
+// parse_attr parses level = "debug", fields(a, b) returning the level,
+// info by default, and the names of the fields
+fn parse_attr(attr: TokenStream) -> (&'static str, Vec<String>) {
+    let mut level = "Info";
+    let mut fields = vec![];
+
+    let tts = attr.into_iter().collect::<Vec<TokenTree>>();
+    for arg in tts.split(|tt| matches!(tt, TokenTree::Punct(pct) if pct.as_char() == ',')) {
+        match arg {
+            [] => (),
+            [TokenTree::Ident(ident), TokenTree::Punct(eq), TokenTree::Literal(lit)]
+                if ident.to_string() == "level" && eq.as_char() == '=' =>
+            {
+                level = level_name(lit.to_string().trim_matches('"'));
+            }
+            [TokenTree::Ident(ident), TokenTree::Group(grp)]
+                if ident.to_string() == "fields" && grp.delimiter() == Delimiter::Parenthesis =>
+            {
+                for field in grp.stream() {
+                    match field {
+                        TokenTree::Ident(ident) => fields.push(ident.to_string()),
+                        TokenTree::Punct(pct) if pct.as_char() == ',' => (),
+                        _ => panic!("#[instrument] fields must be argument names"),
+                    }
+                }
+            }
+            _ => panic!("expected level = \"...\" or fields(...) in #[instrument]"),
+        }
+    }
+
+    (level, fields)
+}
+
+struct Signature {
+    name: String,
+    is_async: bool,
+    // name and type of each argument
+    params: Vec<(Ident, Vec<TokenTree>)>,
+    ret: Option<Vec<TokenTree>>,
+}
+
+impl Signature {
+    // parse parses the tts of a fn up to, but not including, its body
+    fn parse(tts: &[TokenTree]) -> Self {
+        let fn_pos = tts
+            .iter()
+            .position(|tt| matches!(tt, TokenTree::Ident(ident) if ident.to_string() == "fn"))
+            .expect("#[instrument] may only be used on a fn");
+
+        let is_async = tts[..fn_pos]
+            .iter()
+            .any(|tt| matches!(tt, TokenTree::Ident(ident) if ident.to_string() == "async"));
+
+        let name = match tts.get(fn_pos + 1) {
+            Some(TokenTree::Ident(ident)) => ident.to_string(),
+            _ => panic!("expected fn name"),
+        };
+
+        // The params are the first parenthesis group after the name, any
+        // generics come between the two
+        let params_pos = tts[fn_pos..]
+            .iter()
+            .position(|tt| matches!(tt, TokenTree::Group(grp) if grp.delimiter() == Delimiter::Parenthesis))
+            .map(|pos| pos + fn_pos)
+            .expect("expected fn params");
+        let params = match tts[params_pos] {
+            TokenTree::Group(ref grp) => parse_params(grp.stream()),
+            _ => unreachable!(),
+        };
+
+        // -> ${ret} up to a where clause
+        let ret = match &tts[params_pos + 1..] {
+            [TokenTree::Punct(dash), TokenTree::Punct(gt), ret @ ..]
+                if dash.as_char() == '-' && gt.as_char() == '>' =>
+            {
+                let ret = ret
+                    .iter()
+                    .take_while(
+                        |tt| !matches!(tt, TokenTree::Ident(ident) if ident.to_string() == "where"),
+                    )
+                    .cloned()
+                    .collect();
+                Some(ret)
+            }
+            _ => None,
+        };
+
+        Self {
+            name,
+            is_async,
+            params,
+            ret,
+        }
+    }
+
+    // field_entry builds the entry for the argument named field
+    // ${field} : ${type} = ${field}
+    fn field_entry(&self, field: &str) -> TokenStream {
+        let (ident, ty) = match self
+            .params
+            .iter()
+            .find(|(ident, _)| ident.to_string() == field)
+        {
+            Some(param) => param,
+            None => panic!(
+                "#[instrument] field {} is not an argument of {}",
+                field, self.name
+            ),
+        };
+
+        let mut stream = TokenStream::new();
+        stream.extend([TokenTree::Ident(ident.clone()), new_punct(':')]);
+        stream.extend(ty.iter().cloned());
+        stream.extend([new_punct('='), TokenTree::Ident(ident.clone())]);
+        stream
+    }
+
+    // returns_result returns true if the return type is a Result, e.g.
+    // Result<T, E>, io::Result<T> or std::result::Result<T, E>
+    fn returns_result(&self) -> bool {
+        let ret = match self.ret {
+            Some(ref ret) => ret,
+            None => return false,
+        };
+
+        let path_end = ret
+            .iter()
+            .position(|tt| matches!(tt, TokenTree::Punct(pct) if pct.as_char() == '<'))
+            .unwrap_or(ret.len());
+
+        matches!(ret[..path_end].last(), Some(TokenTree::Ident(ident)) if ident.to_string() == "Result")
+    }
+}
+
+// parse_params returns the name and type of each argument, self and
+// destructured arguments are skipped as they can't be fields
+fn parse_params(stream: TokenStream) -> Vec<(Ident, Vec<TokenTree>)> {
+    let mut params = vec![];
+
+    for param in split_params(stream) {
+        let colon = match param
+            .iter()
+            .position(|tt| matches!(tt, TokenTree::Punct(pct) if pct.as_char() == ':'))
+        {
+            Some(colon) => colon,
+            None => continue,
+        };
+
+        // mut ${name}: ${type}
+        let ident = match &param[..colon] {
+            [TokenTree::Ident(ident)] => ident.clone(),
+            [TokenTree::Ident(m), TokenTree::Ident(ident)] if m.to_string() == "mut" => {
+                ident.clone()
+            }
+            _ => continue,
+        };
+
+        params.push((ident, strip_lifetimes(&param[colon + 1..])));
+    }
+
+    params
+}
+
+// split_params splits on commas which aren't inside <> brackets
+fn split_params(stream: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut params = vec![];
+    let mut param = vec![];
+    let mut depth = 0;
+
+    for tt in stream {
+        if let TokenTree::Punct(ref pct) = tt {
+            match pct.as_char() {
+                '<' => depth += 1,
+                '>' if depth > 0 => depth -= 1,
+                ',' if depth == 0 => {
+                    params.push(param);
+                    param = vec![];
+                    continue;
+                }
+                _ => (),
+            }
+        }
+
+        param.push(tt);
+    }
+
+    if !param.is_empty() {
+        params.push(param);
+    }
+
+    params
+}
+
+// strip_lifetimes removes lifetimes from a type so &'a str may be matched
+// the same as &str
+fn strip_lifetimes(ty: &[TokenTree]) -> Vec<TokenTree> {
+    let mut stripped = vec![];
+    let mut it = ty.iter();
+
+    while let Some(tt) = it.next() {
+        if let TokenTree::Punct(pct) = tt {
+            if pct.as_char() == '\'' {
+                // skip the lifetime name, and its comma in Cow<'a, str>
+                it.next();
+                if matches!(it.clone().next(), Some(TokenTree::Punct(pct)) if pct.as_char() == ',')
+                {
+                    it.next();
+                }
+                continue;
+            }
+        }
+
+        stripped.push(tt.clone());
+    }
+
+    stripped
+}
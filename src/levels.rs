@@ -0,0 +1,107 @@
+use proc_macro::{Delimiter, Literal, TokenStream, TokenTree};
+
+use crate::tokens::{new_group, new_ident, new_path, new_punct};
+use crate::{compile_error, maxlevel};
+
+// custom_level!(pub AUDIT, "audit", 11)
+//
+// pub const AUDIT: ndjsonloggercore::Level =
+//     ndjsonloggercore::Level::Custom { name: "audit", severity: 11 };
+pub fn custom_level(ts: TokenStream) -> TokenStream {
+    let tts = ts.into_iter().collect::<Vec<TokenTree>>();
+    let args = tts
+        .split(|tt| matches!(tt, TokenTree::Punct(pct) if pct.as_char() == ','))
+        .collect::<Vec<&[TokenTree]>>();
+
+    let (decl, name, severity) = match args.as_slice() {
+        [decl @ [.., TokenTree::Ident(_)], [TokenTree::Literal(name)], [TokenTree::Literal(severity)]]
+        | [decl @ [.., TokenTree::Ident(_)], [TokenTree::Literal(name)], [TokenTree::Literal(severity)], []] => {
+            (*decl, name, severity)
+        }
+        _ => panic!("custom_level! expects a const name, a level name and a severity, e.g. custom_level!(pub AUDIT, \"audit\", 11)"),
+    };
+
+    let mut stream = decl[..decl.len() - 1]
+        .iter()
+        .cloned()
+        .collect::<TokenStream>();
+    stream.extend([
+        new_ident("const"),
+        decl[decl.len() - 1].clone(),
+        new_punct(':'),
+    ]);
+    stream.extend(new_path(&["ndjsonloggercore", "Level"]));
+    stream.extend([new_punct('=')]);
+    stream.extend(new_path(&["ndjsonloggercore", "Level", "Custom"]));
+
+    let mut fields = TokenStream::new();
+    fields.extend([new_ident("name"), new_punct(':')]);
+    fields.extend(check_name(name));
+    fields.extend([new_punct(','), new_ident("severity"), new_punct(':')]);
+    fields.extend(check_severity(severity));
+    stream.extend([new_group(Delimiter::Brace, fields), new_punct(';')]);
+    stream
+}
+
+// The name is written as the level of each line, so is a lower case string
+// literal which doesn't clash with a built in level
+fn check_name(name: &Literal) -> Vec<TokenTree> {
+    let lit = name.to_string();
+    let valid = match lit.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+        Some(n) => {
+            !n.is_empty()
+                && n.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+                && !maxlevel::is_level(&level_name_title(n))
+        }
+        None => false,
+    };
+
+    if !valid {
+        return compile_error(
+            "custom level names must be lower case string literals not used by a built in level",
+            name.span(),
+        );
+    }
+
+    vec![TokenTree::Literal(name.clone())]
+}
+
+fn check_severity(severity: &Literal) -> Vec<TokenTree> {
+    match severity.to_string().parse::<u8>() {
+        Ok(s) if (maxlevel::MIN_SEVERITY..=maxlevel::MAX_SEVERITY).contains(&s) => {
+            vec![TokenTree::Literal(Literal::u8_suffixed(s))]
+        }
+        _ => compile_error(
+            &format!(
+                "custom level severity must be between {} and {}",
+                maxlevel::MIN_SEVERITY,
+                maxlevel::MAX_SEVERITY
+            ),
+            severity.span(),
+        ),
+    }
+}
+
+// "audit" -> "Audit"
+fn level_name_title(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+// level_name maps a macro name, e.g. info, to its level
+pub fn level_name(name: &str) -> &'static str {
+    match name {
+        "trace" => "Trace",
+        "debug" => "Debug",
+        "info" => "Info",
+        "notice" => "Notice",
+        "warn" => "Warn",
+        "error" => "Error",
+        "critical" => "Critical",
+        "fatal" => "Fatal",
+        _ => panic!("unknown log level {}", name),
+    }
+}
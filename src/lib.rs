@@ -1,71 +1,377 @@
 use proc_macro::token_stream::IntoIter as TTIter;
-use proc_macro::{Ident, Punct, Spacing, Span, TokenStream, TokenTree};
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
 
+mod codes;
+mod context;
+mod instrument;
+mod levels;
+mod logerr;
 mod logfunc;
 use logfunc::LogFunction;
 mod entryiter;
 use entryiter::{EntryIter, EntryLine};
 mod entryvaluebuilder;
+mod location;
+mod maxlevel;
+mod options;
+use options::LogOption;
+mod panichook;
+mod ratelimit;
+mod sample;
+use sample::Sample;
+mod timed;
+mod tokens;
+use tokens::{new_group, new_ident, new_op, new_path};
 mod typefns;
 
-#[cfg(debug_assertions)]
+// debug! lines compile to a no-op in release builds unless the
+// release_max_level_debug or release_max_level_trace feature is enabled
 #[proc_macro]
 pub fn debug(ts: TokenStream) -> TokenStream {
-    log(ts, build_log_level("Debug"))
+    log_with_level(ts, "Debug")
 }
 
-#[cfg(not(debug_assertions))]
+// trace! lines are compiled out entirely unless the trace feature is enabled.
+// When compiled in, a line is only emitted when its mask overlaps the
+// process-wide mask set with ndjsonloggercore::set_trace_mask.
+#[cfg(feature = "trace")]
 #[proc_macro]
-pub fn debug(_: TokenStream) -> TokenStream {
-    TokenStream::new()
+pub fn trace(ts: TokenStream) -> TokenStream {
+    if !maxlevel::level_enabled("Trace") {
+        return compiled_out();
+    }
+
+    let mut it = ts.into_iter();
+
+    // the first argument is the mask expression, e.g. MASK_A | MASK_B
+    let mask = take_until_comma(&mut it);
+    if mask.is_empty() {
+        panic!("trace! must have a mask before the message");
+    }
+
+    let mut log_function = log_function(it, build_log_level("Trace"));
+    log_function.add_condition(trace_condition(mask));
+    log_function.into_token_stream()
+}
+
+#[cfg(not(feature = "trace"))]
+#[proc_macro]
+pub fn trace(_: TokenStream) -> TokenStream {
+    compiled_out()
 }
 
 #[proc_macro]
 pub fn info(ts: TokenStream) -> TokenStream {
-    log(ts, build_log_level("Info"))
+    log_with_level(ts, "Info")
+}
+
+#[proc_macro]
+pub fn notice(ts: TokenStream) -> TokenStream {
+    log_with_level(ts, "Notice")
 }
 
 #[proc_macro]
 pub fn warn(ts: TokenStream) -> TokenStream {
-    log(ts, build_log_level("Warn"))
+    log_with_level(ts, "Warn")
 }
 
 #[proc_macro]
 pub fn error(ts: TokenStream) -> TokenStream {
-    log(ts, build_log_level("Error"))
+    log_with_level(ts, "Error")
+}
+
+#[proc_macro]
+pub fn critical(ts: TokenStream) -> TokenStream {
+    log_with_level(ts, "Critical")
+}
+
+// fatal! only logs, it's up to the caller to exit
+#[proc_macro]
+pub fn fatal(ts: TokenStream) -> TokenStream {
+    log_with_level(ts, "Fatal")
 }
 
-fn log(ts: TokenStream, level: Vec<TokenTree>) -> TokenStream {
+// custom_level! declares a const level with a name and a severity between
+// 1 and 24 for use with log!, higher is more severe. The built in levels are
+// trace 1, debug 5, info 9, notice 10, warn 13, error 17, critical 18 and
+// fatal 21, e.g.
+// custom_level!(pub AUDIT, "audit", 11);
+#[proc_macro]
+pub fn custom_level(ts: TokenStream) -> TokenStream {
+    levels::custom_level(ts)
+}
+
+// log! takes the level as its first argument - any expression evaluating
+// to an ndjsonloggercore::Level - so the level may be chosen at runtime.
+#[proc_macro]
+pub fn log(ts: TokenStream) -> TokenStream {
     let mut it = ts.into_iter();
 
-    // the first tt MUST be a static str
-    let msg = match it.next() {
-        None => panic!("log macros must have message"),
-        Some(tt) => {
-            if let TokenTree::Literal(lit) = tt {
-                lit.to_string()
-            } else {
-                panic!("log macros must have message");
+    let level = take_until_comma(&mut it);
+    if level.is_empty() {
+        panic!("log! must have a level before the message");
+    }
+
+    if maxlevel::no_levels_enabled() {
+        return compiled_out();
+    }
+
+    let mut log_function = log_function(it, level);
+    if !maxlevel::all_levels_enabled() {
+        log_function.add_condition(max_level_condition());
+    }
+
+    log_function.into_token_stream()
+}
+
+// timed! runs a block, returning its value, and logs the time taken in an
+// elapsed_us entry. ? or return leaving the block skip the line, e.g.
+// let rows = timed!(info, warn_above = "100ms", "db query", { table }, { query(table) })?;
+#[proc_macro]
+pub fn timed(ts: TokenStream) -> TokenStream {
+    timed::timed(ts)
+}
+
+// log_err! logs an error line with the error and its source chain if the
+// result is an Err, and returns the result unchanged, e.g.
+// let body = log_err!(fetch(url), "fetch failed", { url })?;
+#[proc_macro]
+pub fn log_err(ts: TokenStream) -> TokenStream {
+    logerr::log_err(ts)
+}
+
+// #[instrument] logs a line on entering a fn, with the selected arguments
+// as entries, and a line on exit with the time taken and, for fns returning
+// a Result, whether it was ok or err, e.g.
+// #[instrument(level = "debug", fields(user_id))]
+#[proc_macro_attribute]
+pub fn instrument(attr: TokenStream, item: TokenStream) -> TokenStream {
+    instrument::instrument(attr, item)
+}
+
+// install_panic_hook! replaces the panic hook with one logging each panic
+// as an error line with its message, location and thread, and with
+// install_panic_hook!(backtrace) a backtrace
+#[proc_macro]
+pub fn install_panic_hook(ts: TokenStream) -> TokenStream {
+    panichook::install_panic_hook(ts)
+}
+
+// fields! builds ndjsonloggercore::Fields from an entries block. Entering
+// the fields adds them to every line logged on the thread until the
+// returned guard is dropped, e.g.
+// let _guard = fields!({ request_id, user_id: u64 = uid }).enter();
+#[proc_macro]
+pub fn fields(ts: TokenStream) -> TokenStream {
+    context::fields(ts)
+}
+
+// with_fields! adds the fields to every line logged while running the
+// block, and returns the value of the block. Given an async block it
+// returns a future which has the fields whenever it's polled, e.g.
+// with_fields!({ request_id }, { handle(request) })
+// with_fields!({ request_id }, async move { handle(request).await }).await
+#[proc_macro]
+pub fn with_fields(ts: TokenStream) -> TokenStream {
+    context::with_fields(ts)
+}
+
+// enabled! returns true if lines of the given ndjsonloggercore::Level are
+// compiled in and enabled at runtime, e.g.
+// if enabled!(Level::Debug) { ... expensive preparation ... }
+#[proc_macro]
+pub fn enabled(ts: TokenStream) -> TokenStream {
+    let level = ts.into_iter().collect::<Vec<TokenTree>>();
+
+    // A level named by path which is compiled out is never enabled
+    match level.last() {
+        None => panic!("enabled! must have a level"),
+        Some(TokenTree::Ident(ident)) => {
+            let name = ident.to_string();
+            if maxlevel::is_level(&name) && !maxlevel::level_enabled(&name) {
+                return TokenStream::from(new_ident("false"));
             }
         }
+        Some(_) => (),
+    }
+
+    // { let __ndjsonlogger_level = ${level}; ${conditions} }
+    let mut stream = logfunc::bind_level(level);
+    if !maxlevel::all_levels_enabled() {
+        stream.extend(max_level_condition());
+        stream.extend(new_op("&&"));
+    }
+    stream.extend(logfunc::level_condition());
+
+    TokenStream::from(new_group(Delimiter::Brace, stream))
+}
+
+fn log_with_level(ts: TokenStream, level: &str) -> TokenStream {
+    if !maxlevel::level_enabled(level) {
+        return compiled_out();
+    }
+
+    log_function(ts.into_iter(), build_log_level(level)).into_token_stream()
+}
+
+// compiled_out is the expansion of a line which is compiled out, an empty
+// block so the macro may still be used as an expression
+fn compiled_out() -> TokenStream {
+    TokenStream::from(new_group(Delimiter::Brace, TokenStream::new()))
+}
+
+fn log_function(it: TTIter, level: Vec<TokenTree>) -> LogFunction {
+    build_log_function(it, level, true)
+}
+
+// log_function_without_location is log_function for lines which carry their
+// own location, e.g. that of a panic, in place of the call site
+fn log_function_without_location(it: TTIter, level: Vec<TokenTree>) -> LogFunction {
+    build_log_function(it, level, false)
+}
+
+#[cfg_attr(not(feature = "location"), allow(unused_variables))]
+fn build_log_function(mut it: TTIter, level: Vec<TokenTree>, location: bool) -> LogFunction {
+    // A logger may be given first, followed by =>
+    let (mut tts, mut more) = take_argument(&mut it);
+    let logger = take_logger(&mut tts);
+
+    // Options come ahead of the message, the message is the first argument
+    // which isn't an option - any &str expression
+    let mut log_options = vec![];
+    let (msg, has_entries) = loop {
+        match options::parse_option(&tts) {
+            Some(log_option) if more => log_options.push(log_option),
+            Some(_) => panic!("log macros must have message"),
+            None => break (tts, more),
+        }
+
+        (tts, more) = take_argument(&mut it);
     };
 
-    let mut log_function = LogFunction::new(level, msg);
+    if msg.is_empty() {
+        panic!("log macros must have message");
+    }
+
+    let mut log_function = LogFunction::new(level, check_msg(msg));
+    if let Some(logger) = logger {
+        log_function.set_logger(logger);
+    }
+
+    let mut sample_rate = None;
+    let mut sample_by = None;
+    for log_option in log_options {
+        match log_option {
+            LogOption::RateLimit(rate_limit) => log_function.set_rate_limit(rate_limit),
+            LogOption::Sample(rate) => sample_rate = Some(rate),
+            LogOption::SampleBy(by) => sample_by = Some(by),
+            LogOption::Code(code) => {
+                add_log_entries_from_group(codes::code_entry(code), &mut log_function)
+            }
+            LogOption::Sink(sink) => log_function.set_sink(sink),
+        }
+    }
+
+    match (sample_rate, sample_by) {
+        (Some(rate), by) => {
+            let sample = Sample::new(rate, by);
+            log_function.add_condition(sample.condition());
+            add_log_entries_from_group(sample.rate_entry(), &mut log_function);
+        }
+        (None, Some(_)) => panic!("sample_by must be given with a sample rate"),
+        (None, None) => (),
+    }
 
-    // Following the message we either have EOS or a comma
-    if let Some(tt) = it.next() {
-        if let TokenTree::Punct(pct) = tt {
+    #[cfg(feature = "location")]
+    if location {
+        add_log_entries_from_group(location::location_entries(), &mut log_function);
+    }
+
+    // Following the comma we have the entries
+    if has_entries {
+        add_log_entries(it, &mut log_function);
+    }
+
+    log_function
+}
+
+// check_msg replaces byte and C string literals, which can never be a &str,
+// with a compile_error! spanned to the literal
+fn check_msg(msg: Vec<TokenTree>) -> Vec<TokenTree> {
+    if let [TokenTree::Literal(lit)] = msg.as_slice() {
+        let repr = lit.to_string();
+        if repr.starts_with('b') || repr.starts_with('c') {
+            return compile_error("log message must be a &str, not a byte string", lit.span());
+        }
+    }
+
+    msg
+}
+
+// compile_error!(${msg}) with the span of the offending tt
+fn compile_error(msg: &str, span: Span) -> Vec<TokenTree> {
+    let mut lit = Literal::string(msg);
+    lit.set_span(span);
+
+    let mut grp = Group::new(
+        Delimiter::Parenthesis,
+        TokenStream::from(TokenTree::Literal(lit)),
+    );
+    grp.set_span(span);
+
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+
+    vec![
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(grp),
+    ]
+}
+
+// take_until_comma consumes tts up to and including the next comma
+fn take_until_comma(it: &mut TTIter) -> Vec<TokenTree> {
+    match take_argument(it) {
+        (tts, true) => tts,
+        (_, false) => panic!("expected , following macro argument"),
+    }
+}
+
+// take_argument consumes tts up to and including the next comma, returning
+// false if the end of the stream was reached instead
+fn take_argument(it: &mut TTIter) -> (Vec<TokenTree>, bool) {
+    let mut tts = vec![];
+
+    for tt in it.by_ref() {
+        if let TokenTree::Punct(ref pct) = tt {
             if pct.as_char() == ',' {
-                add_log_entries(it, &mut log_function);
-            } else {
-                panic!(", is only valid tt following log message");
+                return (tts, true);
             }
-        } else {
-            panic!(", is only valid tt following log message");
         }
+
+        tts.push(tt);
     }
 
-    log_function.into_token_stream()
+    (tts, false)
+}
+
+// take_logger splits ${logger} => off the front of the first argument
+fn take_logger(tts: &mut Vec<TokenTree>) -> Option<Vec<TokenTree>> {
+    let pos = tts.windows(2).position(|w| match w {
+        [TokenTree::Punct(eq), TokenTree::Punct(gt)] => {
+            eq.as_char() == '=' && eq.spacing() == Spacing::Joint && gt.as_char() == '>'
+        }
+        _ => false,
+    })?;
+
+    if pos == 0 {
+        panic!("expected a logger before =>");
+    }
+
+    let rest = tts.split_off(pos + 2);
+    let mut logger = std::mem::replace(tts, rest);
+    logger.truncate(pos);
+    Some(logger)
 }
 
 fn add_log_entries(mut it: TTIter, log_function: &mut LogFunction) {
@@ -91,21 +397,42 @@ fn add_log_entries_from_group(ts: TokenStream, log_function: &mut LogFunction) {
         match entry_line {
             EntryLine::Entry(e) => log_function.add_entry(e.into_entry_args()),
             EntryLine::EntryArray(ea) => log_function.add_entry(ea.into_entry_args()),
+            EntryLine::EntryObject(eo) => log_function.add_entry(eo.into_entry_args()),
         }
     }
 }
 
 // return logger::Level::Info logger::Level::Debug etc.
 fn build_log_level(level: &str) -> Vec<TokenTree> {
-    vec![
-        TokenTree::Ident(Ident::new("ndjsonloggercore", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-        TokenTree::Ident(Ident::new("Level", Span::call_site())),
-        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-        TokenTree::Ident(Ident::new(level, Span::call_site())),
-    ]
+    new_path(&["ndjsonloggercore", "Level", level])
+        .into_iter()
+        .collect()
+}
+
+// ndjsonloggercore::Level::severity(__ndjsonlogger_level) >= ${min severity}
+// so custom levels are compiled out alongside the built in ones
+fn max_level_condition() -> TokenStream {
+    let mut stream = new_path(&["ndjsonloggercore", "Level", "severity"]);
+    stream.extend([new_group(
+        Delimiter::Parenthesis,
+        TokenStream::from(logfunc::level_binding()),
+    )]);
+    stream.extend(new_op(">="));
+    stream.extend([TokenTree::Literal(Literal::u8_suffixed(
+        maxlevel::min_severity(),
+    ))]);
+    stream
+}
+
+// ndjsonloggercore::trace_enabled(${mask})
+#[cfg(feature = "trace")]
+fn trace_condition(mask: Vec<TokenTree>) -> TokenStream {
+    let mut stream = new_path(&["ndjsonloggercore", "trace_enabled"]);
+    stream.extend([new_group(
+        Delimiter::Parenthesis,
+        mask.into_iter().collect(),
+    )]);
+    stream
 }
 
 #[cfg(test)]
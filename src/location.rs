@@ -0,0 +1,65 @@
+// Without the location features only the file and line keys are used, by
+// the panic hook
+#![cfg_attr(not(feature = "location"), allow(dead_code))]
+
+use std::env;
+
+use proc_macro::{Delimiter, TokenStream};
+
+use crate::tokens::{new_group, new_ident, new_punct, new_string};
+
+// The key names may be overridden at compile time, e.g. in .cargo/config.toml
+// [env]
+// NDJSONLOGGER_LINE_KEY = "src_line"
+const MODULE_KEY: (&str, &str) = ("NDJSONLOGGER_MODULE_KEY", "module");
+const FILE_KEY: (&str, &str) = ("NDJSONLOGGER_FILE_KEY", "file");
+const LINE_KEY: (&str, &str) = ("NDJSONLOGGER_LINE_KEY", "line");
+#[cfg(feature = "location_column")]
+const COLUMN_KEY: (&str, &str) = ("NDJSONLOGGER_COLUMN_KEY", "column");
+
+// file_key and line_key are also the keys of a panic's location
+pub fn file_key() -> String {
+    key(FILE_KEY)
+}
+
+pub fn line_key() -> String {
+    key(LINE_KEY)
+}
+
+// location_entries builds the entries group for the call site
+// "module" = module_path!(), "file" = file!(), "line": u32 = line!()
+pub fn location_entries() -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend(location_entry(MODULE_KEY, None, "module_path"));
+    stream.extend([new_punct(',')]);
+    stream.extend(location_entry(FILE_KEY, None, "file"));
+    stream.extend([new_punct(',')]);
+    stream.extend(location_entry(LINE_KEY, Some("u32"), "line"));
+
+    #[cfg(feature = "location_column")]
+    {
+        stream.extend([new_punct(',')]);
+        stream.extend(location_entry(COLUMN_KEY, Some("u32"), "column"));
+    }
+
+    stream
+}
+
+// "${key}" : ${ty} = ${mac}!()
+fn location_entry(key_var: (&str, &str), ty: Option<&str>, mac: &str) -> TokenStream {
+    let mut stream = TokenStream::from(new_string(&key(key_var)));
+    if let Some(ty) = ty {
+        stream.extend([new_punct(':'), new_ident(ty)]);
+    }
+    stream.extend([
+        new_punct('='),
+        new_ident(mac),
+        new_punct('!'),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+    ]);
+    stream
+}
+
+fn key((var, default): (&str, &str)) -> String {
+    env::var(var).unwrap_or_else(|_| default.to_string())
+}
@@ -0,0 +1,152 @@
+use proc_macro::{Delimiter, TokenStream};
+
+use crate::tokens::{new_group, new_ident, new_method_call, new_path, new_punct, new_string};
+use crate::{
+    add_log_entries_from_group, build_log_level, log_function, maxlevel, take_until_comma,
+};
+
+// Keys of the entries holding the error and its source chain
+const ERR_KEY: &str = "err";
+const ERR_CHAIN_KEY: &str = "err_chain";
+
+const RESULT_BINDING: &str = "__ndjsonlogger_result";
+const ERR_BINDING: &str = "__ndjsonlogger_err";
+const ERR_MSG_BINDING: &str = "__ndjsonlogger_err_msg";
+const ERR_CHAIN_BINDING: &str = "__ndjsonlogger_err_chain";
+const SOURCE_BINDING: &str = "__ndjsonlogger_source";
+const S_BINDING: &str = "__ndjsonlogger_s";
+
+// log_err!(${result}, "msg", {...})
+//
+// {
+//     let __ndjsonlogger_result = ${result};
+//     if let Err(ref __ndjsonlogger_err) = __ndjsonlogger_result {
+//         ${log function}
+//     }
+//     __ndjsonlogger_result
+// }
+pub fn log_err(ts: TokenStream) -> TokenStream {
+    let mut it = ts.into_iter();
+
+    let result = take_until_comma(&mut it);
+    if result.is_empty() {
+        panic!("log_err! must have a result before the message");
+    }
+
+    let mut stream = TokenStream::new();
+    stream.extend([new_ident("let"), new_ident(RESULT_BINDING), new_punct('=')]);
+    stream.extend(result);
+    stream.extend([new_punct(';')]);
+
+    if maxlevel::level_enabled("Error") {
+        let mut log_function = log_function(it, build_log_level("Error"));
+        log_function.add_statement(err_statements());
+        add_log_entries_from_group(err_entries(), &mut log_function);
+
+        // if let Err(ref __ndjsonlogger_err) = __ndjsonlogger_result { ... }
+        stream.extend([
+            new_ident("if"),
+            new_ident("let"),
+            new_ident("Err"),
+            new_group(
+                Delimiter::Parenthesis,
+                TokenStream::from_iter([new_ident("ref"), new_ident(ERR_BINDING)]),
+            ),
+            new_punct('='),
+            new_ident(RESULT_BINDING),
+            new_group(Delimiter::Brace, log_function.into_token_stream()),
+        ]);
+    }
+
+    stream.extend([new_ident(RESULT_BINDING)]);
+
+    TokenStream::from(new_group(Delimiter::Brace, stream))
+}
+
+// "err" = &__ndjsonlogger_err_msg, ["err_chain" = __ndjsonlogger_err_chain]
+fn err_entries() -> TokenStream {
+    let mut chain = TokenStream::new();
+    chain.extend([
+        new_string(ERR_CHAIN_KEY),
+        new_punct('='),
+        new_ident(ERR_CHAIN_BINDING),
+    ]);
+
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_string(ERR_KEY),
+        new_punct('='),
+        new_punct('&'),
+        new_ident(ERR_MSG_BINDING),
+        new_punct(','),
+        new_group(Delimiter::Bracket, chain),
+    ]);
+    stream
+}
+
+// err_statements builds the Display form of the error and of each error in
+// its source chain. Method call syntax is used so errors which deref to a
+// dyn Error, e.g. Box<dyn Error>, work too.
+//
+// use std::error::Error as _;
+// let __ndjsonlogger_err_msg = __ndjsonlogger_err.to_string();
+// let mut __ndjsonlogger_err_chain = Vec::new();
+// let mut __ndjsonlogger_source = __ndjsonlogger_err.source();
+// while let Some(__ndjsonlogger_s) = __ndjsonlogger_source {
+//     __ndjsonlogger_err_chain.push(__ndjsonlogger_s.to_string());
+//     __ndjsonlogger_source = __ndjsonlogger_s.source();
+// }
+fn err_statements() -> TokenStream {
+    let mut stream = TokenStream::from(new_ident("use"));
+    stream.extend(new_path(&["std", "error", "Error"]));
+    stream.extend([new_ident("as"), new_ident("_"), new_punct(';')]);
+
+    stream.extend([new_ident("let"), new_ident(ERR_MSG_BINDING), new_punct('=')]);
+    stream.extend(new_method_call(
+        ERR_BINDING,
+        "to_string",
+        TokenStream::new(),
+    ));
+    stream.extend([new_punct(';')]);
+
+    stream.extend([
+        new_ident("let"),
+        new_ident("mut"),
+        new_ident(ERR_CHAIN_BINDING),
+        new_punct('='),
+    ]);
+    stream.extend(new_path(&["Vec", "new"]));
+    stream.extend([
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct(';'),
+    ]);
+
+    stream.extend([
+        new_ident("let"),
+        new_ident("mut"),
+        new_ident(SOURCE_BINDING),
+        new_punct('='),
+    ]);
+    stream.extend(new_method_call(ERR_BINDING, "source", TokenStream::new()));
+    stream.extend([new_punct(';')]);
+
+    let mut body = new_method_call(
+        ERR_CHAIN_BINDING,
+        "push",
+        new_method_call(S_BINDING, "to_string", TokenStream::new()),
+    );
+    body.extend([new_punct(';'), new_ident(SOURCE_BINDING), new_punct('=')]);
+    body.extend(new_method_call(S_BINDING, "source", TokenStream::new()));
+    body.extend([new_punct(';')]);
+
+    stream.extend([
+        new_ident("while"),
+        new_ident("let"),
+        new_ident("Some"),
+        new_group(Delimiter::Parenthesis, new_ident(S_BINDING).into()),
+        new_punct('='),
+        new_ident(SOURCE_BINDING),
+        new_group(Delimiter::Brace, body),
+    ]);
+    stream
+}
@@ -1,76 +1,362 @@
-use std::str::FromStr;
+use proc_macro::{Delimiter, TokenStream, TokenTree};
 
-use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use crate::entryiter::new_entry_args;
+use crate::entryvaluebuilder::build_entry_value;
+use crate::ratelimit::{self, RateLimit};
+use crate::tokens::{new_group, new_ident, new_op, new_path, new_punct};
+use crate::typefns::EntryType;
+
+// The level is bound to this local ahead of the guarded call, so
+// conditions may refer to it without evaluating the level twice
+const LEVEL_BINDING: &str = "__ndjsonlogger_level";
+
+// The context fields in scope are bound to this local ahead of the call
+const CONTEXT_BINDING: &str = "__ndjsonlogger_context";
+
+// Key of the entry holding the unformatted message of a format string message
+const MSG_TEMPLATE_KEY: &str = "\"msg_template\"";
 
 pub struct LogFunction {
     level: Vec<TokenTree>,
-    msg: String,
+    msg: Vec<TokenTree>,
+    format_msg: bool,
     entries: Vec<TokenStream>,
+    conditions: Vec<TokenStream>,
+    rate_limit: Option<RateLimit>,
+    statements: Vec<TokenStream>,
+    logger: Option<Vec<TokenTree>>,
+    sink: Option<TokenStream>,
 }
 
 impl LogFunction {
-    pub fn new(level: Vec<TokenTree>, msg: String) -> Self {
+    pub fn new(level: Vec<TokenTree>, msg: Vec<TokenTree>) -> Self {
+        let mut entries = vec![];
+
+        // Literal messages containing braces are format strings, so {{ and }}
+        // are always unescaped. Messages with {placeholders} keep the
+        // template as an entry so lines may still be grouped by it
+        let (format_msg, placeholders) = match msg.as_slice() {
+            [TokenTree::Literal(lit)] => {
+                let lit = lit.to_string();
+                (has_braces(&lit), has_placeholders(&lit))
+            }
+            _ => (false, false),
+        };
+        if placeholders {
+            entries.push(template_entry(&msg));
+        }
+
         Self {
             level,
             msg,
-            entries: vec![],
+            format_msg,
+            entries,
+            // Entries are only evaluated once the level is known to be enabled
+            conditions: vec![level_condition()],
+            rate_limit: None,
+            statements: vec![],
+            logger: None,
+            sink: None,
         }
     }
 
     pub fn into_token_stream(self) -> TokenStream {
-        let mut out = TokenStream::new();
-        let mut stdout_log_args = TokenStream::new();
+        let mut out = self.statements.into_iter().collect::<TokenStream>();
+        out.extend(bind_context());
+        let mut log_args = TokenStream::new();
 
         // msg
-        stdout_log_args.extend([
-            TokenTree::Literal(Literal::from_str(&self.msg).expect("invalid msg string literal")),
-            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
-        ]);
+        if self.format_msg {
+            log_args.extend(format_msg(self.msg));
+        } else {
+            log_args.extend(self.msg);
+        }
+        log_args.extend([new_punct(',')]);
 
         // level
-        stdout_log_args.extend(self.level);
-        stdout_log_args.extend([TokenTree::Punct(Punct::new(',', Spacing::Alone))]);
-
-        // Tags
-        stdout_log_args.extend([
-            TokenTree::Group(Group::new(Delimiter::Bracket, entries_group(self.entries))),
-            TokenTree::Punct(Punct::new('.', Spacing::Alone)),
-            TokenTree::Ident(Ident::new("into_iter", Span::call_site())),
-            TokenTree::Group(Group::new(Delimiter::Parenthesis, TokenStream::new())),
-        ]);
+        log_args.extend([level_binding()]);
+        log_args.extend([new_punct(',')]);
 
-        out.extend([
-            TokenTree::Ident(Ident::new("ndjsonloggercore", Span::call_site())),
-            TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-            TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-            TokenTree::Ident(Ident::new("stdout_log", Span::call_site())),
-            TokenTree::Group(Group::new(Delimiter::Parenthesis, stdout_log_args)),
+        // Tags, following any context fields in scope
+        // __ndjsonlogger_context.entries().chain([...].into_iter())
+        log_args.extend([
+            new_ident(CONTEXT_BINDING),
+            new_punct('.'),
+            new_ident("entries"),
+            new_group(Delimiter::Parenthesis, TokenStream::new()),
+            new_punct('.'),
+            new_ident("chain"),
+            new_group(Delimiter::Parenthesis, entries_iter(self.entries)),
         ]);
+        if self.rate_limit.is_some() {
+            log_args.extend(ratelimit::chain_suppressed());
+        }
+
+        // ndjsonloggercore::Logger::log(&(${logger}), ${args})
+        // ndjsonloggercore::Logger::log_to(&(${logger}), ${sink}, ${args})
+        // ndjsonloggercore::log_to(${sink}, ${args})
+        // ndjsonloggercore::sink_log(${args}) writing to the registered sink
+        let function = match (self.logger.is_some(), self.sink.is_some()) {
+            (true, true) => "Logger::log_to",
+            (true, false) => "Logger::log",
+            (false, true) => "log_to",
+            (false, false) => "sink_log",
+        };
+        let mut path = vec!["ndjsonloggercore"];
+        path.extend(function.split("::"));
+        let call = new_path(&path);
 
-        out
+        let mut call_args = TokenStream::new();
+        if let Some(logger) = self.logger {
+            call_args.extend([
+                new_punct('&'),
+                new_group(Delimiter::Parenthesis, logger.into_iter().collect()),
+                new_punct(','),
+            ]);
+        }
+        if let Some(sink) = self.sink {
+            call_args.extend(sink);
+            call_args.extend([new_punct(',')]);
+        }
+        call_args.extend(log_args);
+
+        out.extend(call);
+        out.extend([new_group(Delimiter::Parenthesis, call_args)]);
+
+        if let Some(rate_limit) = self.rate_limit {
+            out = rate_limited(rate_limit, out);
+        }
+
+        guard(self.level, self.conditions, out)
     }
 
     pub fn add_entry(&mut self, ts: TokenStream) {
         self.entries.push(ts);
     }
+
+    pub fn set_rate_limit(&mut self, rate_limit: RateLimit) {
+        if self.rate_limit.is_some() {
+            panic!("log macros may have only one of once, first or every");
+        }
+
+        self.rate_limit = Some(rate_limit);
+    }
+
+    // set_logger routes the line through an ndjsonloggercore::Logger, which
+    // adds its bound fields, in place of the global sink_log
+    pub fn set_logger(&mut self, logger: Vec<TokenTree>) {
+        self.logger = Some(logger);
+    }
+
+    // set_sink writes the line to the given sink in place of the registered
+    // sink. A string literal names a sink registered with
+    // ndjsonloggercore::register_sink, anything else is an expression
+    // evaluating to a Sink, e.g. a static.
+    pub fn set_sink(&mut self, sink: Vec<TokenTree>) {
+        if self.sink.is_some() {
+            panic!("log macros may have only one sink");
+        }
+
+        let mut stream = TokenStream::new();
+        match sink.as_slice() {
+            // ndjsonloggercore::named_sink("audit")
+            [TokenTree::Literal(lit)] if lit.to_string().starts_with('"') => {
+                stream.extend(new_path(&["ndjsonloggercore", "named_sink"]));
+                stream.extend([new_group(
+                    Delimiter::Parenthesis,
+                    TokenStream::from(TokenTree::Literal(lit.clone())),
+                )]);
+            }
+            // &(${sink})
+            _ => stream.extend([
+                new_punct('&'),
+                new_group(Delimiter::Parenthesis, sink.into_iter().collect()),
+            ]),
+        }
+
+        self.sink = Some(stream);
+    }
+
+    // add_statement adds a statement run ahead of the call, once the line is
+    // known to be logged, e.g. to prepare values referenced by entries
+    pub fn add_statement(&mut self, ts: TokenStream) {
+        self.statements.push(ts);
+    }
+
+    // add_condition adds a bool expression which must hold for the line to be logged
+    pub fn add_condition(&mut self, ts: TokenStream) {
+        self.conditions.push(ts);
+    }
 }
 
-fn entries_group(entries: Vec<TokenStream>) -> TokenStream {
+// has_braces returns true if the msg literal must be passed through format!
+fn has_braces(msg: &str) -> bool {
+    msg.contains(['{', '}'])
+}
+
+// has_placeholders returns true if the msg literal contains a format
+// placeholder, {{ and }} are escaped braces and not placeholders
+fn has_placeholders(msg: &str) -> bool {
+    let mut chars = msg.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '{' {
+            if chars.peek() == Some(&'{') {
+                chars.next();
+                continue;
+            }
+
+            return true;
+        }
+    }
+
+    false
+}
+
+// &format!(${msg})
+fn format_msg(msg: Vec<TokenTree>) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_punct('&'),
+        new_ident("format"),
+        new_punct('!'),
+        new_group(Delimiter::Parenthesis, msg.into_iter().collect()),
+    ]);
+    stream
+}
+
+// key: "msg_template", value: ${msg} with the braces left unformatted
+fn template_entry(msg: &[TokenTree]) -> TokenStream {
+    new_entry_args(MSG_TEMPLATE_KEY, build_entry_value(EntryType::new(), msg))
+}
+
+// ${statics}
+// if ${check} {
+//     let __ndjsonlogger_suppressed = ...;
+//     ${call};
+// } else {
+//     __NDJSONLOGGER_SUPPRESSED.fetch_add(1, Relaxed);
+// }
+fn rate_limited(rate_limit: RateLimit, call: TokenStream) -> TokenStream {
+    let mut emit = ratelimit::take_suppressed();
+    emit.extend(call);
+    emit.extend([new_punct(';')]);
+
+    let mut stream = rate_limit.statics();
+    stream.extend([new_ident("if")]);
+    stream.extend(rate_limit.check());
+    stream.extend([
+        new_group(Delimiter::Brace, emit),
+        new_ident("else"),
+        new_group(Delimiter::Brace, ratelimit::count_suppressed()),
+    ]);
+    stream
+}
+
+// level_binding is the local the level is bound to in a guarded call
+pub fn level_binding() -> TokenTree {
+    new_ident(LEVEL_BINDING)
+}
+
+// let __ndjsonlogger_level = ${level};
+pub fn bind_level(level: Vec<TokenTree>) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([new_ident("let"), level_binding(), new_punct('=')]);
+    stream.extend(level);
+    stream.extend([new_punct(';')]);
+    stream
+}
+
+// ndjsonloggercore::level_enabled(__ndjsonlogger_level)
+pub fn level_condition() -> TokenStream {
+    let mut stream = new_path(&["ndjsonloggercore", "level_enabled"]);
+    stream.extend([new_group(
+        Delimiter::Parenthesis,
+        TokenStream::from(level_binding()),
+    )]);
+    stream
+}
+
+// {
+//     let __ndjsonlogger_level = ${level};
+//     if ${cond1} && ${cond2} { ${body} }
+// }
+fn guard(level: Vec<TokenTree>, conditions: Vec<TokenStream>, body: TokenStream) -> TokenStream {
+    let mut stream = bind_level(level);
+    stream.extend([new_ident("if")]);
+
+    for (n, c) in conditions.into_iter().enumerate() {
+        if n != 0 {
+            stream.extend(new_op("&&"));
+        }
+
+        stream.extend(c);
+    }
+
+    stream.extend([new_group(Delimiter::Brace, body)]);
+
+    TokenStream::from(new_group(Delimiter::Brace, stream))
+}
+
+// let __ndjsonlogger_context = ndjsonloggercore::context();
+fn bind_context() -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([new_ident("let"), new_ident(CONTEXT_BINDING), new_punct('=')]);
+    stream.extend(new_path(&["ndjsonloggercore", "context"]));
+    stream.extend([
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct(';'),
+    ]);
+    stream
+}
+
+// [ndjsonloggercore::Entry { ... }, ...].into_iter()
+pub fn entries_iter(entries: Vec<TokenStream>) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_group(Delimiter::Bracket, entries_group(entries)),
+        new_punct('.'),
+        new_ident("into_iter"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+    ]);
+    stream
+}
+
+// ndjsonloggercore::Entry { ... }, ...
+pub fn entries_group(entries: Vec<TokenStream>) -> TokenStream {
     let mut stream = TokenStream::new();
 
     for (n, e) in entries.into_iter().enumerate() {
         if n != 0 {
-            stream.extend([TokenTree::Punct(Punct::new(',', Spacing::Alone))]);
+            stream.extend([new_punct(',')]);
         }
 
-        stream.extend([
-            TokenTree::Ident(Ident::new("ndjsonloggercore", Span::call_site())),
-            TokenTree::Punct(Punct::new(':', Spacing::Joint)),
-            TokenTree::Punct(Punct::new(':', Spacing::Alone)),
-            TokenTree::Ident(Ident::new("Entry", Span::call_site())),
-            TokenTree::Group(Group::new(Delimiter::Brace, e)),
-        ]);
+        stream.extend(new_path(&["ndjsonloggercore", "Entry"]));
+        stream.extend([new_group(Delimiter::Brace, e)]);
     }
 
     stream
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        assert!(!has_placeholders("\"user logged in\""));
+        assert!(has_placeholders("\"user {user_id} logged in\""));
+        assert!(has_placeholders("\"{}\""));
+        assert!(!has_placeholders("\"{{literal}}\""));
+        assert!(has_placeholders("\"{{literal}} {user_id}\""));
+        assert!(has_placeholders("\"{{{user_id}}}\""));
+    }
+
+    #[test]
+    fn braces() {
+        assert!(!has_braces("\"user logged in\""));
+        assert!(has_braces("\"{{literal}}\""));
+        assert!(has_braces("\"closing }}\""));
+        assert!(has_braces("\"user {user_id}\""));
+    }
+}
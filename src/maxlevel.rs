@@ -0,0 +1,216 @@
+// Levels and their severity, higher is more severe. The gaps leave room for
+// custom levels between the built in ones.
+const LEVELS: [(&str, u8); 8] = [
+    ("Trace", 1),
+    ("Debug", 5),
+    ("Info", 9),
+    ("Notice", 10),
+    ("Warn", 13),
+    ("Error", 17),
+    ("Critical", 18),
+    ("Fatal", 21),
+];
+
+// Custom levels may have any severity in this range
+pub const MIN_SEVERITY: u8 = 1;
+pub const MAX_SEVERITY: u8 = 24;
+
+// level_enabled returns false if lines of the level should compile to a
+// no-op, trace lines are only compiled in with the trace feature
+pub fn level_enabled(level: &str) -> bool {
+    (level != "Trace" || cfg!(feature = "trace")) && severity(level) >= min_severity()
+}
+
+// is_level returns true if level names one of the levels, e.g. "Debug"
+pub fn is_level(level: &str) -> bool {
+    LEVELS.iter().any(|(name, _)| *name == level)
+}
+
+// severity returns the severity of one of the levels
+pub fn severity(level: &str) -> u8 {
+    match LEVELS.iter().find(|(name, _)| *name == level) {
+        Some((_, severity)) => *severity,
+        None => panic!("unknown log level {}", level),
+    }
+}
+
+// no_levels_enabled returns true if every level is compiled out
+pub fn no_levels_enabled() -> bool {
+    min_severity() > MAX_SEVERITY
+}
+
+// all_levels_enabled returns true if no level is compiled out
+pub fn all_levels_enabled() -> bool {
+    min_severity() <= MIN_SEVERITY
+}
+
+// min_severity returns the lowest severity which is compiled in
+pub fn min_severity() -> u8 {
+    resolve_min_severity(
+        cfg!(debug_assertions),
+        release_max_level(),
+        static_max_level(),
+    )
+}
+
+// resolve_min_severity picks the lowest severity compiled in. Release
+// builds use the release_max_level_* features falling back to max_level_*
+// and then info. Debug builds use max_level_* falling back to trace.
+fn resolve_min_severity(debug: bool, release_max: Option<u8>, max: Option<u8>) -> u8 {
+    if !debug {
+        if let Some(min) = release_max {
+            return min;
+        }
+
+        return max.unwrap_or(severity("Info"));
+    }
+
+    max.unwrap_or(MIN_SEVERITY)
+}
+
+fn static_max_level() -> Option<u8> {
+    most_restrictive([
+        (cfg!(feature = "max_level_off"), MAX_SEVERITY + 1),
+        (cfg!(feature = "max_level_error"), severity("Error")),
+        (cfg!(feature = "max_level_warn"), severity("Warn")),
+        (cfg!(feature = "max_level_info"), severity("Info")),
+        (cfg!(feature = "max_level_debug"), severity("Debug")),
+        (cfg!(feature = "max_level_trace"), severity("Trace")),
+    ])
+}
+
+fn release_max_level() -> Option<u8> {
+    most_restrictive([
+        (cfg!(feature = "release_max_level_off"), MAX_SEVERITY + 1),
+        (cfg!(feature = "release_max_level_error"), severity("Error")),
+        (cfg!(feature = "release_max_level_warn"), severity("Warn")),
+        (cfg!(feature = "release_max_level_info"), severity("Info")),
+        (cfg!(feature = "release_max_level_debug"), severity("Debug")),
+        (cfg!(feature = "release_max_level_trace"), severity("Trace")),
+    ])
+}
+
+// If several features are enabled the most restrictive wins
+fn most_restrictive(features: [(bool, u8); 6]) -> Option<u8> {
+    features
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, min)| *min)
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFF: u8 = MAX_SEVERITY + 1;
+
+    #[test]
+    fn debug_builds() {
+        assert_eq!(resolve_min_severity(true, None, None), severity("Trace"));
+        assert_eq!(
+            resolve_min_severity(true, None, Some(severity("Warn"))),
+            severity("Warn")
+        );
+        // release_max_level_* features don't apply
+        assert_eq!(
+            resolve_min_severity(true, Some(severity("Error")), None),
+            severity("Trace")
+        );
+        assert_eq!(resolve_min_severity(true, None, Some(OFF)), OFF);
+    }
+
+    #[test]
+    fn release_builds() {
+        assert_eq!(resolve_min_severity(false, None, None), severity("Info"));
+        assert_eq!(
+            resolve_min_severity(false, None, Some(severity("Debug"))),
+            severity("Debug")
+        );
+        assert_eq!(
+            resolve_min_severity(false, Some(severity("Error")), Some(severity("Debug"))),
+            severity("Error")
+        );
+        assert_eq!(resolve_min_severity(false, Some(OFF), None), OFF);
+    }
+
+    #[test]
+    fn most_restrictive_wins() {
+        let features = |enabled: [bool; 6]| {
+            let mut features = [
+                (false, OFF),
+                (false, 17),
+                (false, 13),
+                (false, 9),
+                (false, 5),
+                (false, 1),
+            ];
+            for (feature, enabled) in features.iter_mut().zip(enabled) {
+                feature.0 = enabled;
+            }
+            most_restrictive(features)
+        };
+
+        assert_eq!(features([false; 6]), None);
+        assert_eq!(
+            features([false, false, true, false, false, false]),
+            Some(13)
+        );
+        assert_eq!(features([false, true, false, true, false, true]), Some(17));
+        assert_eq!(
+            features([true, false, false, false, false, true]),
+            Some(OFF)
+        );
+    }
+
+    // The features this crate is built with, e.g.
+    // cargo test --release --features release_max_level_warn
+    #[test]
+    fn enabled_features() {
+        let max = if cfg!(feature = "max_level_off") {
+            Some(OFF)
+        } else if cfg!(feature = "max_level_error") {
+            Some(severity("Error"))
+        } else if cfg!(feature = "max_level_warn") {
+            Some(severity("Warn"))
+        } else if cfg!(feature = "max_level_info") {
+            Some(severity("Info"))
+        } else if cfg!(feature = "max_level_debug") {
+            Some(severity("Debug"))
+        } else if cfg!(feature = "max_level_trace") {
+            Some(severity("Trace"))
+        } else {
+            None
+        };
+        let release_max = if cfg!(feature = "release_max_level_off") {
+            Some(OFF)
+        } else if cfg!(feature = "release_max_level_error") {
+            Some(severity("Error"))
+        } else if cfg!(feature = "release_max_level_warn") {
+            Some(severity("Warn"))
+        } else if cfg!(feature = "release_max_level_info") {
+            Some(severity("Info"))
+        } else if cfg!(feature = "release_max_level_debug") {
+            Some(severity("Debug"))
+        } else if cfg!(feature = "release_max_level_trace") {
+            Some(severity("Trace"))
+        } else {
+            None
+        };
+
+        let expected = if cfg!(debug_assertions) {
+            max.unwrap_or(severity("Trace"))
+        } else {
+            release_max.or(max).unwrap_or(severity("Info"))
+        };
+
+        assert_eq!(min_severity(), expected);
+        assert_eq!(no_levels_enabled(), expected == OFF);
+        assert_eq!(all_levels_enabled(), expected == severity("Trace"));
+        assert_eq!(level_enabled("Debug"), expected <= severity("Debug"));
+        assert_eq!(
+            level_enabled("Trace"),
+            cfg!(feature = "trace") && expected == severity("Trace")
+        );
+    }
+}
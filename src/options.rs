@@ -0,0 +1,117 @@
+use proc_macro::{Spacing, TokenTree};
+
+use crate::ratelimit::RateLimit;
+
+// Options are given ahead of the log message, e.g.
+// warn!(every = "10s", "msg", {...})
+// warn!(once, "msg")
+// info!(sample = 0.01, sample_by = trace_id, "msg")
+// error!(code = "AUTH-0042", "msg")
+// info!(sink = AUDIT, "msg")
+pub enum LogOption {
+    RateLimit(RateLimit),
+    // sample = ${rate}
+    Sample(Vec<TokenTree>),
+    // sample_by = ${key}
+    SampleBy(Vec<TokenTree>),
+    // code = "AUTH-0042"
+    Code(Vec<TokenTree>),
+    // sink = AUDIT or sink = "audit"
+    Sink(Vec<TokenTree>),
+}
+
+// parse_option returns None if tts is not an option, in which case tts is
+// the log message
+pub fn parse_option(tts: &[TokenTree]) -> Option<LogOption> {
+    let name = match tts.first() {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => return None,
+    };
+
+    // bare options
+    if tts.len() == 1 {
+        return match option_name(&name, true)? {
+            OptionName::Once => Some(LogOption::RateLimit(RateLimit::Once)),
+            _ => None,
+        };
+    }
+
+    // name = value options, an = joint with a following tt is => or ==
+    let is_assign = matches!(
+        tts.get(1),
+        Some(TokenTree::Punct(pct)) if pct.as_char() == '=' && pct.spacing() == Spacing::Alone
+    );
+    if !is_assign {
+        return None;
+    }
+
+    let value = &tts[2..];
+    if value.is_empty() {
+        panic!("expected value following {} =", name);
+    }
+
+    match option_name(&name, false)? {
+        OptionName::Every => Some(LogOption::RateLimit(RateLimit::every(value))),
+        OptionName::First => Some(LogOption::RateLimit(RateLimit::First(value.to_vec()))),
+        OptionName::Sample => Some(LogOption::Sample(value.to_vec())),
+        OptionName::SampleBy => Some(LogOption::SampleBy(value.to_vec())),
+        OptionName::Code => Some(LogOption::Code(value.to_vec())),
+        OptionName::Sink => Some(LogOption::Sink(value.to_vec())),
+        OptionName::Once => None,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum OptionName {
+    Once,
+    Every,
+    First,
+    Sample,
+    SampleBy,
+    Code,
+    Sink,
+}
+
+// option_name returns the option called name, None if there's no such
+// option. Bare options are given without a value, the rest as name = value.
+fn option_name(name: &str, bare: bool) -> Option<OptionName> {
+    match (name, bare) {
+        ("once", true) => Some(OptionName::Once),
+        ("every", false) => Some(OptionName::Every),
+        ("first", false) => Some(OptionName::First),
+        ("sample", false) => Some(OptionName::Sample),
+        ("sample_by", false) => Some(OptionName::SampleBy),
+        ("code", false) => Some(OptionName::Code),
+        ("sink", false) => Some(OptionName::Sink),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_names() {
+        assert_eq!(option_name("once", true), Some(OptionName::Once));
+        assert_eq!(option_name("every", false), Some(OptionName::Every));
+        assert_eq!(option_name("first", false), Some(OptionName::First));
+        assert_eq!(option_name("sample", false), Some(OptionName::Sample));
+        assert_eq!(option_name("sample_by", false), Some(OptionName::SampleBy));
+        assert_eq!(option_name("code", false), Some(OptionName::Code));
+        assert_eq!(option_name("sink", false), Some(OptionName::Sink));
+    }
+
+    #[test]
+    fn not_options() {
+        // once takes no value, the rest must have one
+        assert_eq!(option_name("once", false), None);
+        assert_eq!(option_name("every", true), None);
+        assert_eq!(option_name("sink", true), None);
+        // A message held in a variable, or a message ident = ..., e.g. a
+        // const named like an option, is left to the message
+        assert_eq!(option_name("msg", true), None);
+        assert_eq!(option_name("level", false), None);
+        assert_eq!(option_name("Once", true), None);
+    }
+}
@@ -0,0 +1,256 @@
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+use crate::tokens::{
+    new_group, new_ident, new_method_call, new_op, new_path, new_punct, new_string,
+};
+use crate::{
+    add_log_entries_from_group, build_log_level, compiled_out, location,
+    log_function_without_location, maxlevel,
+};
+
+// Keys of the entries describing where the panic happened, the file and
+// line keys are shared with the location features
+const THREAD_KEY: &str = "thread";
+const BACKTRACE_KEY: &str = "backtrace";
+
+const PANIC_BINDING: &str = "__ndjsonlogger_panic";
+const PAYLOAD_BINDING: &str = "__ndjsonlogger_payload";
+const PANIC_MSG_BINDING: &str = "__ndjsonlogger_panic_msg";
+const LOCATION_BINDING: &str = "__ndjsonlogger_location";
+const THREAD_BINDING: &str = "__ndjsonlogger_thread";
+const BACKTRACE_BINDING: &str = "__ndjsonlogger_backtrace";
+const S_BINDING: &str = "__ndjsonlogger_s";
+
+// install_panic_hook!() or install_panic_hook!(backtrace)
+//
+// std::panic::set_hook(std::boxed::Box::new(|__ndjsonlogger_panic| {
+//     ${panic statements}
+//     ${log function}
+// }))
+pub fn install_panic_hook(ts: TokenStream) -> TokenStream {
+    let backtrace = match ts.into_iter().collect::<Vec<TokenTree>>().as_slice() {
+        [] => false,
+        [TokenTree::Ident(ident)] if ident.to_string() == "backtrace" => true,
+        _ => panic!("install_panic_hook! expects no arguments or backtrace"),
+    };
+
+    // With error lines compiled out the default hook is left in place
+    if !maxlevel::level_enabled("Error") {
+        return compiled_out();
+    }
+
+    // The panic message is the message of the line. The location features'
+    // file and line would be those of the install_panic_hook! call, so the
+    // line only carries the location of the panic.
+    let mut log_function = log_function_without_location(
+        TokenStream::from(new_ident(PANIC_MSG_BINDING)).into_iter(),
+        build_log_level("Error"),
+    );
+    log_function.add_statement(panic_statements(backtrace));
+    add_log_entries_from_group(panic_entries(backtrace), &mut log_function);
+
+    // std::panic::set_hook(std::boxed::Box::new(|__ndjsonlogger_panic| { ... }))
+    let mut hook = TokenStream::new();
+    hook.extend([
+        new_punct('|'),
+        new_ident(PANIC_BINDING),
+        new_punct('|'),
+        new_group(Delimiter::Brace, log_function.into_token_stream()),
+    ]);
+
+    let mut boxed_hook = new_path(&["std", "boxed", "Box", "new"]);
+    boxed_hook.extend([new_group(Delimiter::Parenthesis, hook)]);
+
+    let mut stream = new_path(&["std", "panic", "set_hook"]);
+    stream.extend([new_group(Delimiter::Parenthesis, boxed_hook)]);
+    stream
+}
+
+// "file" : Option<&str> = __ndjsonlogger_location.map(|l| l.file()),
+// "line" : Option<u32> = __ndjsonlogger_location.map(|l| l.line()),
+// "thread" : Option<&str> = __ndjsonlogger_thread.name()
+// and with a backtrace, "backtrace" = &__ndjsonlogger_backtrace
+fn panic_entries(backtrace: bool) -> TokenStream {
+    let mut stream = TokenStream::new();
+
+    for (key, ty, method) in [
+        (location::file_key(), "&str", "file"),
+        (location::line_key(), "u32", "line"),
+    ] {
+        let mut location = TokenStream::new();
+        location.extend([
+            new_punct('|'),
+            new_ident("l"),
+            new_punct('|'),
+            new_ident("l"),
+            new_punct('.'),
+            new_ident(method),
+            new_group(Delimiter::Parenthesis, TokenStream::new()),
+        ]);
+
+        stream.extend([new_string(&key), new_punct(':')]);
+        stream.extend(new_option_type(ty));
+        stream.extend([
+            new_punct('='),
+            new_ident(LOCATION_BINDING),
+            new_punct('.'),
+            new_ident("map"),
+            new_group(Delimiter::Parenthesis, location),
+            new_punct(','),
+        ]);
+    }
+
+    stream.extend([new_string(THREAD_KEY), new_punct(':')]);
+    stream.extend(new_option_type("&str"));
+    stream.extend([
+        new_punct('='),
+        new_ident(THREAD_BINDING),
+        new_punct('.'),
+        new_ident("name"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+    ]);
+
+    if backtrace {
+        stream.extend([
+            new_punct(','),
+            new_string(BACKTRACE_KEY),
+            new_punct('='),
+            new_punct('&'),
+            new_ident(BACKTRACE_BINDING),
+        ]);
+    }
+
+    stream
+}
+
+// Option<${ty}>, where ty is an ident or &str
+fn new_option_type(ty: &str) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([new_ident("Option"), new_punct('<')]);
+    match ty.strip_prefix('&') {
+        Some(ty) => stream.extend([new_punct('&'), new_ident(ty)]),
+        None => stream.extend([new_ident(ty)]),
+    }
+    stream.extend([new_punct('>')]);
+    stream
+}
+
+// panic_statements pulls the message, location and thread out of the panic.
+// Payloads are a &str or String for panics with a message, anything else
+// came from std::panic::panic_any.
+//
+// let __ndjsonlogger_payload = __ndjsonlogger_panic.payload();
+// let __ndjsonlogger_panic_msg: &str =
+//     if let Some(__ndjsonlogger_s) = __ndjsonlogger_payload.downcast_ref::<&str>() {
+//         __ndjsonlogger_s
+//     } else if let Some(__ndjsonlogger_s) = __ndjsonlogger_payload.downcast_ref::<String>() {
+//         __ndjsonlogger_s.as_str()
+//     } else {
+//         "Box<dyn Any>"
+//     };
+// let __ndjsonlogger_location = __ndjsonlogger_panic.location();
+// let __ndjsonlogger_thread = std::thread::current();
+// let __ndjsonlogger_backtrace = std::backtrace::Backtrace::force_capture().to_string();
+fn panic_statements(backtrace: bool) -> TokenStream {
+    let mut stream = new_let(PAYLOAD_BINDING);
+    stream.extend(new_method_call(
+        PANIC_BINDING,
+        "payload",
+        TokenStream::new(),
+    ));
+    stream.extend([new_punct(';')]);
+
+    stream.extend([
+        new_ident("let"),
+        new_ident(PANIC_MSG_BINDING),
+        new_punct(':'),
+        new_punct('&'),
+        new_ident("str"),
+        new_punct('='),
+    ]);
+    stream.extend(downcast_payload(
+        "&str",
+        TokenStream::from(new_ident(S_BINDING)),
+    ));
+    stream.extend([new_ident("else")]);
+    stream.extend(downcast_payload(
+        "String",
+        new_method_call(S_BINDING, "as_str", TokenStream::new()),
+    ));
+    stream.extend([
+        new_ident("else"),
+        new_group(
+            Delimiter::Brace,
+            TokenStream::from(new_string("Box<dyn Any>")),
+        ),
+        new_punct(';'),
+    ]);
+
+    stream.extend(new_let(LOCATION_BINDING));
+    stream.extend(new_method_call(
+        PANIC_BINDING,
+        "location",
+        TokenStream::new(),
+    ));
+    stream.extend([new_punct(';')]);
+
+    stream.extend(new_let(THREAD_BINDING));
+    stream.extend(new_path(&["std", "thread", "current"]));
+    stream.extend([
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct(';'),
+    ]);
+
+    if backtrace {
+        stream.extend(new_let(BACKTRACE_BINDING));
+        stream.extend(new_path(&[
+            "std",
+            "backtrace",
+            "Backtrace",
+            "force_capture",
+        ]));
+        stream.extend([
+            new_group(Delimiter::Parenthesis, TokenStream::new()),
+            new_punct('.'),
+            new_ident("to_string"),
+            new_group(Delimiter::Parenthesis, TokenStream::new()),
+            new_punct(';'),
+        ]);
+    }
+
+    stream
+}
+
+// if let Some(__ndjsonlogger_s) = __ndjsonlogger_payload.downcast_ref::<${ty}>() { ${body} }
+fn downcast_payload(ty: &str, body: TokenStream) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_ident("if"),
+        new_ident("let"),
+        new_ident("Some"),
+        new_group(Delimiter::Parenthesis, new_ident(S_BINDING).into()),
+        new_punct('='),
+        new_ident(PAYLOAD_BINDING),
+        new_punct('.'),
+        new_ident("downcast_ref"),
+    ]);
+    stream.extend(new_op("::"));
+    stream.extend([new_punct('<')]);
+    match ty.strip_prefix('&') {
+        Some(ty) => stream.extend([new_punct('&'), new_ident(ty)]),
+        None => stream.extend([new_ident(ty)]),
+    }
+    stream.extend([
+        new_punct('>'),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_group(Delimiter::Brace, body),
+    ]);
+    stream
+}
+
+// let ${binding} =
+fn new_let(binding: &str) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([new_ident("let"), new_ident(binding), new_punct('=')]);
+    stream
+}
@@ -0,0 +1,245 @@
+use proc_macro::{Delimiter, Literal, TokenStream, TokenTree};
+
+use crate::tokens::{new_group, new_ident, new_op, new_path, new_punct, new_string};
+
+// Key of the entry holding the number of lines suppressed since the last
+// line emitted by the call site
+const SUPPRESSED_KEY: &str = "suppressed";
+
+// Count of lines suppressed since the call site last emitted, and the local
+// it's taken into when a line is emitted
+const SUPPRESSED_STATIC: &str = "__NDJSONLOGGER_SUPPRESSED";
+const SUPPRESSED_BINDING: &str = "__ndjsonlogger_suppressed";
+
+// Each rate limited call site gets its own statics, declared inside the
+// block generated for the call so they never clash between call sites
+pub enum RateLimit {
+    // once
+    Once,
+    // first = ${expr}
+    First(Vec<TokenTree>),
+    // every = "10s", the period in nanoseconds
+    Every(u64),
+}
+
+impl RateLimit {
+    pub fn every(value: &[TokenTree]) -> Self {
+        let period = match value {
+            [TokenTree::Literal(lit)] => parse_period(&lit.to_string()),
+            _ => None,
+        };
+
+        match period {
+            Some(period) => RateLimit::Every(period),
+            None => panic!("every = expects a period string literal, e.g. \"500ms\" or \"10s\""),
+        }
+    }
+
+    // statics declares the per call site state
+    // static __NDJSONLOGGER_SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+    // static ${state}: ${type} = ${type}::new(${initial});
+    pub fn statics(&self) -> TokenStream {
+        let mut stream = new_static(SUPPRESSED_STATIC, "AtomicU64", new_u64(0));
+        stream.extend(match self {
+            RateLimit::Once => new_static(
+                "__NDJSONLOGGER_FIRED",
+                "AtomicBool",
+                new_ident("false").into(),
+            ),
+            RateLimit::First(_) => new_static("__NDJSONLOGGER_COUNT", "AtomicU64", new_u64(0)),
+            RateLimit::Every(_) => new_static("__NDJSONLOGGER_LAST", "AtomicU64", new_u64(0)),
+        });
+        stream
+    }
+
+    // check is a bool expression, true if the line should be emitted
+    pub fn check(self) -> TokenStream {
+        match self {
+            // !__NDJSONLOGGER_FIRED.swap(true, Relaxed)
+            RateLimit::Once => {
+                let mut stream = TokenStream::from(new_punct('!'));
+                stream.extend(new_atomic_call(
+                    "__NDJSONLOGGER_FIRED",
+                    "swap",
+                    new_ident("true").into(),
+                ));
+                stream
+            }
+            // __NDJSONLOGGER_COUNT.fetch_add(1, Relaxed) < (${n}) as u64
+            RateLimit::First(n) => {
+                let mut stream = new_atomic_call("__NDJSONLOGGER_COUNT", "fetch_add", new_u64(1));
+                stream.extend([
+                    new_punct('<'),
+                    new_group(Delimiter::Parenthesis, n.into_iter().collect()),
+                    new_ident("as"),
+                    new_ident("u64"),
+                ]);
+                stream
+            }
+            // ndjsonloggercore::period_elapsed(&__NDJSONLOGGER_LAST, ${period})
+            RateLimit::Every(period) => {
+                let mut args = TokenStream::new();
+                args.extend([
+                    new_punct('&'),
+                    new_ident("__NDJSONLOGGER_LAST"),
+                    new_punct(','),
+                    TokenTree::Literal(Literal::u64_suffixed(period)),
+                ]);
+
+                let mut stream = new_path(&["ndjsonloggercore", "period_elapsed"]);
+                stream.extend([new_group(Delimiter::Parenthesis, args)]);
+                stream
+            }
+        }
+    }
+}
+
+// let __ndjsonlogger_suppressed = __NDJSONLOGGER_SUPPRESSED.swap(0, Relaxed);
+pub fn take_suppressed() -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_ident("let"),
+        new_ident(SUPPRESSED_BINDING),
+        new_punct('='),
+    ]);
+    stream.extend(new_atomic_call(SUPPRESSED_STATIC, "swap", new_u64(0)));
+    stream.extend([new_punct(';')]);
+    stream
+}
+
+// __NDJSONLOGGER_SUPPRESSED.fetch_add(1, Relaxed);
+pub fn count_suppressed() -> TokenStream {
+    let mut stream = new_atomic_call(SUPPRESSED_STATIC, "fetch_add", new_u64(1));
+    stream.extend([new_punct(';')]);
+    stream
+}
+
+// .chain((__ndjsonlogger_suppressed > 0).then(|| Entry { key: "suppressed", ... }))
+pub fn chain_suppressed() -> TokenStream {
+    let mut cond = TokenStream::new();
+    cond.extend([new_ident(SUPPRESSED_BINDING), new_punct('>')]);
+    cond.extend(new_u64(0));
+
+    // key: "suppressed", value: Value::Atom(Atom::Uint(__ndjsonlogger_suppressed))
+    let mut uint = new_path(&["ndjsonloggercore", "Atom", "Uint"]);
+    uint.extend([new_group(
+        Delimiter::Parenthesis,
+        new_ident(SUPPRESSED_BINDING).into(),
+    )]);
+    let mut fields = TokenStream::new();
+    fields.extend([
+        new_ident("key"),
+        new_punct(':'),
+        new_string(SUPPRESSED_KEY),
+        new_punct(','),
+        new_ident("value"),
+        new_punct(':'),
+    ]);
+    fields.extend(new_path(&["ndjsonloggercore", "Value", "Atom"]));
+    fields.extend([new_group(Delimiter::Parenthesis, uint)]);
+
+    let mut entry = new_op("||");
+    entry.extend(new_path(&["ndjsonloggercore", "Entry"]));
+    entry.extend([new_group(Delimiter::Brace, fields)]);
+
+    let mut then = TokenStream::new();
+    then.extend([
+        new_group(Delimiter::Parenthesis, cond),
+        new_punct('.'),
+        new_ident("then"),
+        new_group(Delimiter::Parenthesis, entry),
+    ]);
+
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_punct('.'),
+        new_ident("chain"),
+        new_group(Delimiter::Parenthesis, then),
+    ]);
+    stream
+}
+
+// static ${name}: std::sync::atomic::${ty} = std::sync::atomic::${ty}::new(${initial});
+fn new_static(name: &str, ty: &str, initial: TokenStream) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([new_ident("static"), new_ident(name), new_punct(':')]);
+    stream.extend(new_path(&["std", "sync", "atomic", ty]));
+    stream.extend([new_punct('=')]);
+    stream.extend(new_path(&["std", "sync", "atomic", ty, "new"]));
+    stream.extend([new_group(Delimiter::Parenthesis, initial), new_punct(';')]);
+    stream
+}
+
+// ${name}.${method}(${arg}, std::sync::atomic::Ordering::Relaxed)
+fn new_atomic_call(name: &str, method: &str, arg: TokenStream) -> TokenStream {
+    let mut args = arg;
+    args.extend([new_punct(',')]);
+    args.extend(new_path(&["std", "sync", "atomic", "Ordering", "Relaxed"]));
+
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_ident(name),
+        new_punct('.'),
+        new_ident(method),
+        new_group(Delimiter::Parenthesis, args),
+    ]);
+    stream
+}
+
+fn new_u64(n: u64) -> TokenStream {
+    TokenStream::from(TokenTree::Literal(Literal::u64_unsuffixed(n)))
+}
+
+// parse_period parses a string literal such as "500ms" or "10s" to nanoseconds
+pub fn parse_period(lit: &str) -> Option<u64> {
+    let period = lit.strip_prefix('"')?.strip_suffix('"')?;
+    let unit_pos = period.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = period.split_at(unit_pos);
+    let n = n.parse::<u64>().ok()?;
+
+    let nanos = match unit {
+        "ns" => 1,
+        "us" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        "m" => 60_000_000_000,
+        "h" => 3_600_000_000_000,
+        _ => return None,
+    };
+
+    n.checked_mul(nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periods() {
+        assert_eq!(parse_period("\"250ns\""), Some(250));
+        assert_eq!(parse_period("\"10us\""), Some(10_000));
+        assert_eq!(parse_period("\"500ms\""), Some(500_000_000));
+        assert_eq!(parse_period("\"10s\""), Some(10_000_000_000));
+        assert_eq!(parse_period("\"2m\""), Some(120_000_000_000));
+        assert_eq!(parse_period("\"1h\""), Some(3_600_000_000_000));
+        assert_eq!(parse_period("\"0s\""), Some(0));
+    }
+
+    #[test]
+    fn invalid_periods() {
+        // Not a string literal
+        assert_eq!(parse_period("10"), None);
+        assert_eq!(parse_period("\"10s"), None);
+        // Missing number or unit
+        assert_eq!(parse_period("\"10\""), None);
+        assert_eq!(parse_period("\"ms\""), None);
+        assert_eq!(parse_period("\"\""), None);
+        // Unknown units, fractions and spaces
+        assert_eq!(parse_period("\"10d\""), None);
+        assert_eq!(parse_period("\"1.5s\""), None);
+        assert_eq!(parse_period("\"10 s\""), None);
+        assert_eq!(parse_period("\"-1s\""), None);
+        // Overflows u64 nanoseconds
+        assert_eq!(parse_period("\"6000000h\""), None);
+    }
+}
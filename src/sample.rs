@@ -0,0 +1,168 @@
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+use crate::tokens::{new_group, new_ident, new_path, new_punct, new_string};
+
+// Key of the entry holding the rate a line was sampled at
+const SAMPLE_RATE_KEY: &str = "sample_rate";
+
+const HASHER_BINDING: &str = "__ndjsonlogger_hasher";
+
+// sample = ${rate} keeps a random fraction of lines, with sample_by = ${key}
+// the fraction is chosen by hashing key so every line logged for a key is
+// either kept or dropped together
+pub struct Sample {
+    rate: Vec<TokenTree>,
+    by: Option<Vec<TokenTree>>,
+}
+
+// valid_rate returns true for a literal rate greater than 0 and at most 1
+fn valid_rate(rate: &str) -> bool {
+    matches!(rate.parse::<f64>(), Ok(rate) if rate > 0.0 && rate <= 1.0)
+}
+
+impl Sample {
+    pub fn new(rate: Vec<TokenTree>, by: Option<Vec<TokenTree>>) -> Self {
+        if let [TokenTree::Literal(lit)] = rate.as_slice() {
+            if !valid_rate(&lit.to_string()) {
+                panic!("sample = expects a rate greater than 0 and at most 1");
+            }
+        }
+
+        Self { rate, by }
+    }
+
+    // condition is a bool expression, true if the line is sampled
+    // ((${hash}) as f64) < (${rate}) as f64 * u64::MAX as f64
+    pub fn condition(&self) -> TokenStream {
+        let mut hash = TokenStream::new();
+        hash.extend([
+            new_group(Delimiter::Parenthesis, self.hash()),
+            new_ident("as"),
+            new_ident("f64"),
+        ]);
+
+        let mut stream = TokenStream::new();
+        stream.extend([
+            new_group(Delimiter::Parenthesis, hash),
+            new_punct('<'),
+            new_group(Delimiter::Parenthesis, self.rate.iter().cloned().collect()),
+            new_ident("as"),
+            new_ident("f64"),
+            new_punct('*'),
+        ]);
+        stream.extend(new_path(&["u64", "MAX"]));
+        stream.extend([new_ident("as"), new_ident("f64")]);
+        stream
+    }
+
+    // rate_entry builds the entries group recording the sample rate, cast
+    // so integer rates, e.g. sample = 1, are floats
+    // "sample_rate" : f64 = (${rate}) as f64
+    pub fn rate_entry(self) -> TokenStream {
+        let mut stream = TokenStream::new();
+        stream.extend([
+            new_string(SAMPLE_RATE_KEY),
+            new_punct(':'),
+            new_ident("f64"),
+            new_punct('='),
+            new_group(Delimiter::Parenthesis, self.rate.into_iter().collect()),
+            new_ident("as"),
+            new_ident("f64"),
+        ]);
+        stream
+    }
+
+    fn hash(&self) -> TokenStream {
+        match self.by {
+            // DefaultHasher::new() always uses the same keys, so a key hashes
+            // to the same value on every call
+            // {
+            //     let mut __ndjsonlogger_hasher = DefaultHasher::new();
+            //     std::hash::Hash::hash(&(${by}), &mut __ndjsonlogger_hasher);
+            //     std::hash::Hasher::finish(&__ndjsonlogger_hasher)
+            // }
+            Some(ref by) => {
+                let mut hash_args = TokenStream::new();
+                hash_args.extend([
+                    new_punct('&'),
+                    new_group(Delimiter::Parenthesis, by.iter().cloned().collect()),
+                    new_punct(','),
+                    new_punct('&'),
+                    new_ident("mut"),
+                    new_ident(HASHER_BINDING),
+                ]);
+
+                let mut stream = TokenStream::new();
+                stream.extend([
+                    new_ident("let"),
+                    new_ident("mut"),
+                    new_ident(HASHER_BINDING),
+                    new_punct('='),
+                ]);
+                stream.extend(new_path(&[
+                    "std",
+                    "collections",
+                    "hash_map",
+                    "DefaultHasher",
+                    "new",
+                ]));
+                stream.extend([
+                    new_group(Delimiter::Parenthesis, TokenStream::new()),
+                    new_punct(';'),
+                ]);
+                stream.extend(new_path(&["std", "hash", "Hash", "hash"]));
+                stream.extend([new_group(Delimiter::Parenthesis, hash_args), new_punct(';')]);
+                stream.extend(new_path(&["std", "hash", "Hasher", "finish"]));
+                stream.extend([new_group(
+                    Delimiter::Parenthesis,
+                    TokenStream::from_iter([new_punct('&'), new_ident(HASHER_BINDING)]),
+                )]);
+
+                TokenStream::from(new_group(Delimiter::Brace, stream))
+            }
+            // Every RandomState has different keys, so hashing nothing
+            // gives a new random value on every call
+            // std::hash::Hasher::finish(&std::hash::BuildHasher::build_hasher(
+            //     &std::collections::hash_map::RandomState::new(),
+            // ))
+            None => {
+                let mut random_state = TokenStream::from(new_punct('&'));
+                random_state.extend(new_path(&[
+                    "std",
+                    "collections",
+                    "hash_map",
+                    "RandomState",
+                    "new",
+                ]));
+                random_state.extend([new_group(Delimiter::Parenthesis, TokenStream::new())]);
+
+                let mut hasher = TokenStream::from(new_punct('&'));
+                hasher.extend(new_path(&["std", "hash", "BuildHasher", "build_hasher"]));
+                hasher.extend([new_group(Delimiter::Parenthesis, random_state)]);
+
+                let mut stream = new_path(&["std", "hash", "Hasher", "finish"]);
+                stream.extend([new_group(Delimiter::Parenthesis, hasher)]);
+                stream
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates() {
+        for rate in ["1.0", "1", "0.5", "0.01", "1e-3"] {
+            assert!(valid_rate(rate), "{}", rate);
+        }
+    }
+
+    #[test]
+    fn invalid_rates() {
+        for rate in ["0", "0.0", "-0.5", "1.5", "2", "NaN", "0.5f32", "\"0.5\""] {
+            assert!(!valid_rate(rate), "{}", rate);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use proc_macro::{Delimiter, Literal, TokenStream, TokenTree};
+
+use crate::levels::level_name;
+use crate::ratelimit::parse_period;
+use crate::tokens::{new_group, new_ident, new_op, new_path, new_punct, new_string};
+use crate::{
+    add_log_entries_from_group, build_log_level, log_function, max_level_condition, maxlevel,
+    take_until_comma,
+};
+
+// Key of the entry holding the time taken to run the block
+const ELAPSED_KEY: &str = "elapsed_us";
+
+const START_BINDING: &str = "__ndjsonlogger_start";
+const ELAPSED_BINDING: &str = "__ndjsonlogger_elapsed_us";
+const RET_BINDING: &str = "__ndjsonlogger_ret";
+
+// timed!(info, "db query", { table }, { ...block... })
+// timed!(info, warn_above = "100ms", "db query", { table }, { ...block... })
+//
+// {
+//     let __ndjsonlogger_start = std::time::Instant::now();
+//     let __ndjsonlogger_ret = { ...block... };
+//     let __ndjsonlogger_elapsed_us = __ndjsonlogger_start.elapsed().as_micros() as u64;
+//     ${log function}
+//     __ndjsonlogger_ret
+// }
+//
+// The block is inlined so it may await, but ?, return or break leaving the
+// block skip the line. Callers apply ? to the value of timed! instead.
+pub fn timed(ts: TokenStream) -> TokenStream {
+    let mut it = ts.into_iter();
+
+    let level = match take_until_comma(&mut it).as_slice() {
+        [TokenTree::Ident(ident)] => level_name(&ident.to_string()),
+        _ => panic!("timed! must have a level, e.g. info, before the message"),
+    };
+
+    let mut args = it.collect::<Vec<TokenTree>>();
+
+    // The block is always the last argument
+    let block = match args.pop() {
+        Some(TokenTree::Group(grp)) if grp.delimiter() == Delimiter::Brace => grp,
+        _ => panic!("timed! must end with a block"),
+    };
+    match args.pop() {
+        Some(TokenTree::Punct(pct)) if pct.as_char() == ',' => (),
+        _ => panic!("expected , before timed! block"),
+    }
+
+    // warn_above = "100ms" escalates the line to warn when the block is slow
+    let warn_above = match args.as_slice() {
+        [TokenTree::Ident(ident), TokenTree::Punct(eq), TokenTree::Literal(lit), TokenTree::Punct(comma), ..]
+            if ident.to_string() == "warn_above"
+                && eq.as_char() == '='
+                && comma.as_char() == ',' =>
+        {
+            if maxlevel::severity(level) >= maxlevel::severity("Warn") {
+                panic!("warn_above may only be given for levels below warn");
+            }
+
+            let warn_above = match parse_period(&lit.to_string()) {
+                Some(nanos) => nanos / 1_000,
+                None => panic!("warn_above = expects a period string literal, e.g. \"100ms\""),
+            };
+            args.drain(..4);
+            Some(warn_above)
+        }
+        _ => None,
+    };
+
+    let mut stream = start_timer();
+    stream.extend([
+        new_ident("let"),
+        new_ident(RET_BINDING),
+        new_punct('='),
+        TokenTree::Group(block),
+        new_punct(';'),
+    ]);
+    stream.extend(bind_elapsed_us());
+
+    let log_line = match warn_above {
+        Some(warn_above) if maxlevel::level_enabled(level) || maxlevel::level_enabled("Warn") => {
+            let mut log_function = log_function(
+                args.into_iter().collect::<TokenStream>().into_iter(),
+                escalating_level(level, warn_above),
+            );
+            if !maxlevel::all_levels_enabled() {
+                log_function.add_condition(max_level_condition());
+            }
+
+            Some(log_function)
+        }
+        None if maxlevel::level_enabled(level) => Some(log_function(
+            args.into_iter().collect::<TokenStream>().into_iter(),
+            build_log_level(level),
+        )),
+        _ => None,
+    };
+
+    if let Some(mut log_function) = log_line {
+        add_log_entries_from_group(elapsed_entry(), &mut log_function);
+
+        stream.extend(log_function.into_token_stream());
+        stream.extend([new_punct(';')]);
+    }
+
+    stream.extend([new_ident(RET_BINDING)]);
+
+    TokenStream::from(new_group(Delimiter::Brace, stream))
+}
+
+// let __ndjsonlogger_start = std::time::Instant::now();
+pub fn start_timer() -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([new_ident("let"), new_ident(START_BINDING), new_punct('=')]);
+    stream.extend(new_path(&["std", "time", "Instant", "now"]));
+    stream.extend([
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct(';'),
+    ]);
+    stream
+}
+
+// let __ndjsonlogger_elapsed_us = __ndjsonlogger_start.elapsed().as_micros() as u64;
+pub fn bind_elapsed_us() -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_ident("let"),
+        new_ident(ELAPSED_BINDING),
+        new_punct('='),
+        new_ident(START_BINDING),
+        new_punct('.'),
+        new_ident("elapsed"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_punct('.'),
+        new_ident("as_micros"),
+        new_group(Delimiter::Parenthesis, TokenStream::new()),
+        new_ident("as"),
+        new_ident("u64"),
+        new_punct(';'),
+    ]);
+    stream
+}
+
+// "elapsed_us" : u64 = __ndjsonlogger_elapsed_us
+pub fn elapsed_entry() -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_string(ELAPSED_KEY),
+        new_punct(':'),
+        new_ident("u64"),
+        new_punct('='),
+        new_ident(ELAPSED_BINDING),
+    ]);
+    stream
+}
+
+// if __ndjsonlogger_elapsed_us >= ${warn_above} { Level::Warn } else { ${level} }
+fn escalating_level(level: &str, warn_above: u64) -> Vec<TokenTree> {
+    let mut tts = vec![new_ident("if"), new_ident(ELAPSED_BINDING)];
+    tts.extend(new_op(">="));
+    tts.extend([
+        TokenTree::Literal(Literal::u64_suffixed(warn_above)),
+        new_group(
+            Delimiter::Brace,
+            build_log_level("Warn").into_iter().collect(),
+        ),
+        new_ident("else"),
+        new_group(
+            Delimiter::Brace,
+            build_log_level(level).into_iter().collect(),
+        ),
+    ]);
+    tts
+}
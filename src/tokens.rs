@@ -0,0 +1,62 @@
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+// Builders for the token trees of generated code, spanned at the call site
+
+pub fn new_ident(ident: &str) -> TokenTree {
+    TokenTree::Ident(Ident::new(ident, Span::call_site()))
+}
+
+pub fn new_punct(ch: char) -> TokenTree {
+    TokenTree::Punct(Punct::new(ch, Spacing::Alone))
+}
+
+// new_op builds a multi character operator, e.g. "&&", ">=" or "::"
+pub fn new_op(op: &str) -> TokenStream {
+    let mut chars = op.chars().peekable();
+    let mut stream = TokenStream::new();
+
+    while let Some(ch) = chars.next() {
+        let spacing = match chars.peek() {
+            Some(_) => Spacing::Joint,
+            None => Spacing::Alone,
+        };
+        stream.extend([TokenTree::Punct(Punct::new(ch, spacing))]);
+    }
+
+    stream
+}
+
+// new_path builds a path from its segments, e.g. std::time::Instant::now
+pub fn new_path(segments: &[&str]) -> TokenStream {
+    let mut stream = TokenStream::new();
+
+    for (n, segment) in segments.iter().enumerate() {
+        if n != 0 {
+            stream.extend(new_op("::"));
+        }
+
+        stream.extend([new_ident(segment)]);
+    }
+
+    stream
+}
+
+pub fn new_group(delimiter: Delimiter, stream: TokenStream) -> TokenTree {
+    TokenTree::Group(Group::new(delimiter, stream))
+}
+
+// ${receiver}.${method}(${args})
+pub fn new_method_call(receiver: &str, method: &str, args: TokenStream) -> TokenStream {
+    let mut stream = TokenStream::new();
+    stream.extend([
+        new_ident(receiver),
+        new_punct('.'),
+        new_ident(method),
+        new_group(Delimiter::Parenthesis, args),
+    ]);
+    stream
+}
+
+pub fn new_string(s: &str) -> TokenTree {
+    TokenTree::Literal(Literal::string(s))
+}
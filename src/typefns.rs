@@ -1,32 +1,91 @@
-use proc_macro::TokenTree;
+use proc_macro::{Delimiter, TokenTree};
+
+// TypeToken is a token of a declared type. Types are matched on these rather
+// than on TokenTrees, which only exist while a macro expands, so matching
+// types may be unit tested.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TypeToken {
+    Ident(String),
+    Punct(char),
+    Group(Delimiter, Vec<TypeToken>),
+    Literal(String),
+}
+
+impl TypeToken {
+    fn from_tts<'a>(tts: impl IntoIterator<Item = &'a TokenTree>) -> Vec<Self> {
+        tts.into_iter()
+            .map(|tt| match tt {
+                TokenTree::Ident(ident) => TypeToken::Ident(ident.to_string()),
+                TokenTree::Punct(pct) => TypeToken::Punct(pct.as_char()),
+                TokenTree::Group(grp) => TypeToken::Group(
+                    grp.delimiter(),
+                    TypeToken::from_tts(&grp.stream().into_iter().collect::<Vec<TokenTree>>()),
+                ),
+                TokenTree::Literal(lit) => TypeToken::Literal(lit.to_string()),
+            })
+            .collect()
+    }
+
+    fn is_ident(&self, name: &str) -> bool {
+        matches!(self, TypeToken::Ident(ident) if ident == name)
+    }
+
+    fn is_punct(&self, ch: char) -> bool {
+        *self == TypeToken::Punct(ch)
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct EntryType {
     atom_type: AtomType,
     opt: bool,
+    // ${type} as str, integers written as decimal strings
+    quoted: bool,
 }
 
 impl EntryType {
     pub fn new() -> Self {
         Self {
-            atom_type: AtomType::String,
+            atom_type: AtomType::Str,
             opt: false,
+            quoted: false,
         }
     }
 
     pub fn from_type(tts: &[&TokenTree]) -> Self {
+        Self::from_tokens(&TypeToken::from_tts(tts.iter().copied()))
+    }
+
+    fn from_tokens(tokens: &[TypeToken]) -> Self {
+        // ${type} as str
+        if let [type_tokens @ .., as_, str_] = tokens {
+            if as_.is_ident("as") && str_.is_ident("str") {
+                let entry_type = Self::from_tokens(type_tokens);
+                if !entry_type.atom_type.is_integer() {
+                    panic!("only integer types may be written as str");
+                }
+
+                return Self {
+                    quoted: true,
+                    ..entry_type
+                };
+            }
+        }
+
         for (atom_type, type_fn) in TYPE_FNS.iter() {
-            if type_fn(tts) {
+            if type_fn(tokens) {
                 return Self {
                     atom_type: *atom_type,
                     opt: false,
+                    quoted: false,
                 };
             }
 
-            if type_fn_opt(tts, type_fn) {
+            if type_fn_opt(tokens, type_fn) {
                 return Self {
                     atom_type: *atom_type,
                     opt: true,
+                    quoted: false,
                 };
             }
         }
@@ -39,17 +98,167 @@ impl EntryType {
     }
 
     pub fn is_str(self) -> bool {
-        self.atom_type == AtomType::String
+        matches!(self.atom_type, AtomType::Str | AtomType::OwnedStr)
+    }
+
+    // is_owned_str returns true for the owned and shared string types, which
+    // are borrowed through AsRef<str>. Untyped and &str values are passed as
+    // is, so deref coercion applies, e.g. &Arc<String>.
+    pub fn is_owned_str(self) -> bool {
+        self.atom_type == AtomType::OwnedStr
     }
 
     pub fn atom_type(self) -> (&'static str, Option<&'static str>) {
-        self.atom_type.atom_type()
+        if self.quoted {
+            self.atom_type.quoted_atom_type()
+        } else {
+            self.atom_type.atom_type()
+        }
+    }
+}
+
+// ArrayType is the type of an array entry, declared as the type of its
+// elements. The array itself may be an Option, declared as Option<&[T]>.
+#[derive(Clone)]
+pub struct ArrayType {
+    element: ElementType,
+    opt: bool,
+}
+
+impl ArrayType {
+    pub fn new() -> Self {
+        Self {
+            element: ElementType::Atom(EntryType::new()),
+            opt: false,
+        }
+    }
+
+    pub fn from_type(tts: &[&TokenTree]) -> Self {
+        Self::from_tokens(&TypeToken::from_tts(tts.iter().copied()))
+    }
+
+    fn from_tokens(tokens: &[TypeToken]) -> Self {
+        // Option < & [${element}] >
+        if let Some(element) = type_opt_slice(tokens) {
+            return Self {
+                element: ElementType::from_tokens(element),
+                opt: true,
+            };
+        }
+
+        Self {
+            element: ElementType::from_tokens(tokens),
+            opt: false,
+        }
+    }
+
+    pub fn element(&self) -> &ElementType {
+        &self.element
+    }
+
+    pub fn is_opt(&self) -> bool {
+        self.opt
+    }
+}
+
+// ElementType is the type of the elements of an array
+// T, Option<T>:  an atom
+// &[T]:          an array, Option<&[T]> for arrays which may be null
+// { id: u32 }:   an object of the fields of each element, untyped fields
+//                are &str
+#[derive(Clone)]
+pub enum ElementType {
+    Atom(EntryType),
+    Array(Box<ArrayType>),
+    Object(Vec<(String, ElementType)>),
+}
+
+impl ElementType {
+    fn from_tokens(tokens: &[TypeToken]) -> Self {
+        match tokens {
+            [TypeToken::Group(Delimiter::Brace, fields)] => {
+                return ElementType::Object(parse_fields(fields));
+            }
+            // & [${element}]
+            [amp, TypeToken::Group(Delimiter::Bracket, element)] if amp.is_punct('&') => {
+                return ElementType::Array(Box::new(ArrayType {
+                    element: ElementType::from_tokens(element),
+                    opt: false,
+                }));
+            }
+            _ => (),
+        }
+
+        if type_opt_slice(tokens).is_some() {
+            return ElementType::Array(Box::new(ArrayType::from_tokens(tokens)));
+        }
+
+        ElementType::Atom(EntryType::from_tokens(tokens))
+    }
+}
+
+// parse_fields parses the fields of an object element type
+// { ${field}, ${field} : ${type}, ... }
+fn parse_fields(tokens: &[TypeToken]) -> Vec<(String, ElementType)> {
+    // Split on commas outside of < >, e.g. not that of Cow<'a, str>
+    let mut angle_depth = 0;
+    let split = tokens.split(|token| match token {
+        TypeToken::Punct('<') => {
+            angle_depth += 1;
+            false
+        }
+        TypeToken::Punct('>') => {
+            angle_depth -= 1;
+            false
+        }
+        TypeToken::Punct(',') => angle_depth == 0,
+        _ => false,
+    });
+
+    let mut fields = vec![];
+    for field in split {
+        let name = match field.first() {
+            Some(TypeToken::Ident(ident)) => ident.clone(),
+            // Trailing comma
+            None => continue,
+            _ => panic!("expected field name in object type"),
+        };
+
+        let element = match field.get(1) {
+            None => ElementType::Atom(EntryType::new()),
+            Some(TypeToken::Punct(':')) if field.len() > 2 => ElementType::from_tokens(&field[2..]),
+            _ => panic!("expected : and type following field {}", name),
+        };
+
+        fields.push((name, element));
+    }
+
+    if fields.is_empty() {
+        panic!("object types must have at least one field");
     }
+
+    fields
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+// type_opt_slice returns the element tokens of Option<&[${element}]>
+fn type_opt_slice(tokens: &[TypeToken]) -> Option<&[TypeToken]> {
+    match tokens {
+        [option, lt, amp, TypeToken::Group(Delimiter::Bracket, element), gt]
+            if option.is_ident("Option")
+                && lt.is_punct('<')
+                && amp.is_punct('&')
+                && gt.is_punct('>') =>
+        {
+            Some(element)
+        }
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum AtomType {
-    String,
+    Str,
+    OwnedStr,
     U64,
     I64,
     U32,
@@ -62,13 +271,16 @@ enum AtomType {
     F32,
     F16,
     Usize,
+    Isize,
+    I128,
+    U128,
     Bool,
 }
 
 impl AtomType {
     fn atom_type(self) -> (&'static str, Option<&'static str>) {
         match self {
-            AtomType::String => ("String", None),
+            AtomType::Str | AtomType::OwnedStr => ("String", None),
             AtomType::U64 => ("Uint", None),
             AtomType::I64 => ("Int", None),
             AtomType::U32 => ("Uint", Some("u64")),
@@ -81,14 +293,44 @@ impl AtomType {
             AtomType::F32 => ("Float", Some("f64")),
             AtomType::F16 => ("Float", Some("f64")),
             AtomType::Usize => ("Uint", Some("u64")),
+            AtomType::Isize => ("Int", Some("i64")),
+            // Numbers if JSON safe, otherwise decimal strings
+            AtomType::I128 => ("Int128", None),
+            AtomType::U128 => ("Uint128", None),
             AtomType::Bool => ("Bool", None),
         }
     }
+
+    // quoted_atom_type is the atom of integers always written as decimal
+    // strings
+    fn quoted_atom_type(self) -> (&'static str, Option<&'static str>) {
+        match self {
+            AtomType::I128 => ("StrInt", None),
+            AtomType::U128 => ("StrUint", None),
+            AtomType::I64 | AtomType::I32 | AtomType::I16 | AtomType::I8 | AtomType::Isize => {
+                ("StrInt", Some("i128"))
+            }
+            _ => ("StrUint", Some("u128")),
+        }
+    }
+
+    fn is_integer(self) -> bool {
+        !matches!(
+            self,
+            AtomType::Str
+                | AtomType::OwnedStr
+                | AtomType::F64
+                | AtomType::F32
+                | AtomType::F16
+                | AtomType::Bool
+        )
+    }
 }
 
 #[allow(clippy::type_complexity)]
-const TYPE_FNS: &[(AtomType, fn(&[&TokenTree]) -> bool)] = &[
-    (AtomType::String, type_fn_string),
+const TYPE_FNS: &[(AtomType, fn(&[TypeToken]) -> bool)] = &[
+    (AtomType::Str, type_fn_str),
+    (AtomType::OwnedStr, type_fn_owned_str),
     (AtomType::U64, type_fn_u64),
     (AtomType::I64, type_fn_i64),
     (AtomType::U32, type_fn_u32),
@@ -101,111 +343,413 @@ const TYPE_FNS: &[(AtomType, fn(&[&TokenTree]) -> bool)] = &[
     (AtomType::F32, type_fn_f32),
     (AtomType::F16, type_fn_f16),
     (AtomType::Usize, type_fn_usize),
+    (AtomType::Isize, type_fn_isize),
+    (AtomType::I128, type_fn_i128),
+    (AtomType::U128, type_fn_u128),
     (AtomType::Bool, type_fn_bool),
 ];
 
-fn type_fn_string(tts: &[&TokenTree]) -> bool {
-    if tts.len() != 2 {
-        return false;
+fn type_fn_str(tokens: &[TypeToken]) -> bool {
+    string_type(tokens) == Some(AtomType::Str)
+}
+
+fn type_fn_owned_str(tokens: &[TypeToken]) -> bool {
+    string_type(tokens) == Some(AtomType::OwnedStr)
+}
+
+// string_type returns the atom type of the types borrowed as a &str
+// Str:      &str
+// OwnedStr: String, &String, Box<str>, Arc<str>, Rc<str>, Cow<str>,
+//           Cow<'a, str> and the same by path, e.g. std::sync::Arc<str>
+fn string_type(tokens: &[TypeToken]) -> Option<AtomType> {
+    use TypeToken::{Ident, Punct};
+
+    // Strip any & or &'a
+    let (borrowed, mut tokens) = match tokens {
+        [Punct('&'), Punct('\''), Ident(_), rest @ ..] => (true, rest),
+        [Punct('&'), rest @ ..] => (true, rest),
+        _ => (false, tokens),
+    };
+
+    // Strip any path, e.g. std::sync::Arc<str>
+    while let [Ident(_), Punct(':'), Punct(':'), rest @ ..] = tokens {
+        tokens = rest;
     }
 
-    if let Some(TokenTree::Punct(pct)) = tts.get(0) {
-        if pct.as_char() == '&' {
-            if let Some(TokenTree::Ident(ident)) = tts.get(1) {
-                if ident.to_string() == "str" {
-                    return true;
-                }
-            }
+    let is_str = |token: &TypeToken| token.is_ident("str");
+    match tokens {
+        [str_] if borrowed && is_str(str_) => Some(AtomType::Str),
+        [Ident(string)] if string == "String" => Some(AtomType::OwnedStr),
+        [Ident(ptr), Punct('<'), str_, Punct('>')]
+            if matches!(ptr.as_str(), "Box" | "Arc" | "Rc" | "Cow") && is_str(str_) =>
+        {
+            Some(AtomType::OwnedStr)
+        }
+        [Ident(cow), Punct('<'), Punct('\''), Ident(_), Punct(','), str_, Punct('>')]
+            if cow == "Cow" && is_str(str_) =>
+        {
+            Some(AtomType::OwnedStr)
         }
+        _ => None,
     }
+}
 
-    false
+fn type_fn_u64(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "u64")
 }
 
-fn type_fn_u64(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "u64")
+fn type_fn_i64(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "i64")
 }
 
-fn type_fn_i64(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "i64")
+fn type_fn_u32(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "u32")
 }
 
-fn type_fn_u32(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "u32")
+fn type_fn_i32(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "i32")
 }
 
-fn type_fn_i32(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "i32")
+fn type_fn_u16(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "u16")
 }
 
-fn type_fn_u16(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "u16")
+fn type_fn_i16(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "i16")
 }
 
-fn type_fn_i16(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "i16")
+fn type_fn_u8(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "u8")
 }
 
-fn type_fn_u8(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "u8")
+fn type_fn_i8(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "i8")
 }
 
-fn type_fn_i8(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "i8")
+fn type_fn_f64(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "f64")
 }
 
-fn type_fn_f64(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "f64")
+fn type_fn_f32(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "f32")
 }
 
-fn type_fn_f32(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "f32")
+fn type_fn_f16(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "f16")
 }
 
-fn type_fn_f16(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "f16")
+fn type_fn_usize(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "usize")
 }
 
-fn type_fn_usize(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "usize")
+fn type_fn_isize(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "isize")
 }
 
-fn type_fn_bool(tts: &[&TokenTree]) -> bool {
-    type_fn_single_ident(tts, "bool")
+fn type_fn_i128(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "i128")
 }
 
-fn type_fn_single_ident(tts: &[&TokenTree], ident_str: &str) -> bool {
-    if tts.len() != 1 {
-        return false;
-    }
+fn type_fn_u128(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "u128")
+}
 
-    if let Some(TokenTree::Ident(ident)) = tts.get(0) {
-        if ident.to_string() == ident_str {
-            return true;
-        }
-    }
+fn type_fn_bool(tokens: &[TypeToken]) -> bool {
+    type_fn_single_ident(tokens, "bool")
+}
 
-    false
+fn type_fn_single_ident(tokens: &[TypeToken], ident_str: &str) -> bool {
+    matches!(tokens, [ident] if ident.is_ident(ident_str))
 }
 
-fn type_fn_opt(tts: &[&TokenTree], inner_type_fn: &fn(&[&TokenTree]) -> bool) -> bool {
-    if tts.len() < 4 {
-        return false;
+fn type_fn_opt(tokens: &[TypeToken], inner_type_fn: &fn(&[TypeToken]) -> bool) -> bool {
+    match tokens {
+        [option, lt, inner @ .., gt]
+            if !inner.is_empty()
+                && option.is_ident("Option")
+                && lt.is_punct('<')
+                && gt.is_punct('>') =>
+        {
+            inner_type_fn(inner)
+        }
+        _ => false,
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tokens splits a type the way the compiler does, e.g. Cow<'a, str> is
+    // Cow < ' a , str >
+    fn tokens(ty: &str) -> Vec<TypeToken> {
+        fn parse(chars: &mut std::iter::Peekable<std::str::Chars>) -> Vec<TypeToken> {
+            let mut tokens = vec![];
+            while let Some(c) = chars.next() {
+                let delimiter = match c {
+                    '(' => Some(Delimiter::Parenthesis),
+                    '[' => Some(Delimiter::Bracket),
+                    '{' => Some(Delimiter::Brace),
+                    _ => None,
+                };
 
-    if let Some(TokenTree::Ident(ident)) = tts.get(0) {
-        if ident.to_string() == "Option" {
-            if let Some(TokenTree::Punct(pct)) = tts.get(1) {
-                if pct.as_char() == '<' {
-                    if let Some(TokenTree::Punct(pct)) = tts.get(tts.len() - 1) {
-                        if pct.as_char() == '>' {
-                            return inner_type_fn(&tts[2..(tts.len() - 1)]);
+                if let Some(delimiter) = delimiter {
+                    tokens.push(TypeToken::Group(delimiter, parse(chars)));
+                } else if matches!(c, ')' | ']' | '}') {
+                    break;
+                } else if c.is_alphanumeric() || c == '_' {
+                    let mut ident = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_alphanumeric() || c == '_') {
+                            break;
                         }
+                        ident.push(c);
+                        chars.next();
                     }
+                    tokens.push(TypeToken::Ident(ident));
+                } else if !c.is_whitespace() {
+                    tokens.push(TypeToken::Punct(c));
                 }
             }
+            tokens
+        }
+
+        parse(&mut ty.chars().peekable())
+    }
+
+    fn entry_type(ty: &str) -> EntryType {
+        EntryType::from_tokens(&tokens(ty))
+    }
+
+    fn array_type(ty: &str) -> ArrayType {
+        ArrayType::from_tokens(&tokens(ty))
+    }
+
+    // The atom type and optionality of an atom element
+    fn atom(element: &ElementType) -> (AtomType, bool) {
+        match element {
+            ElementType::Atom(entry_type) => (entry_type.atom_type, entry_type.opt),
+            _ => panic!("expected an atom element"),
+        }
+    }
+
+    #[test]
+    fn array_of_atoms() {
+        let array = array_type("u32");
+        assert!(!array.is_opt());
+        assert_eq!(atom(array.element()), (AtomType::U32, false));
+
+        let array = array_type("&str");
+        assert_eq!(atom(array.element()), (AtomType::Str, false));
+    }
+
+    #[test]
+    fn array_of_options() {
+        // &[Option<T>] is declared as the element type Option<T>
+        let array = array_type("Option<u32>");
+        assert!(!array.is_opt());
+        assert_eq!(atom(array.element()), (AtomType::U32, true));
+
+        let array = array_type("Option<&str>");
+        assert_eq!(atom(array.element()), (AtomType::Str, true));
+    }
+
+    #[test]
+    fn optional_array() {
+        let array = array_type("Option<&[f64]>");
+        assert!(array.is_opt());
+        assert_eq!(atom(array.element()), (AtomType::F64, false));
+
+        let array = array_type("Option<&[Option<&str>]>");
+        assert!(array.is_opt());
+        assert_eq!(atom(array.element()), (AtomType::Str, true));
+    }
+
+    #[test]
+    fn optional_entry() {
+        let entry = entry_type("Option<u64>");
+        assert!(entry.is_opt());
+        assert_eq!(entry.atom_type, AtomType::U64);
+
+        let entry = entry_type("bool");
+        assert!(!entry.is_opt());
+        assert_eq!(entry.atom_type(), ("Bool", None));
+    }
+
+    #[test]
+    fn nested_arrays() {
+        let array = array_type("&[i64]");
+        let inner = match array.element() {
+            ElementType::Array(inner) => inner,
+            _ => panic!("expected an array element"),
+        };
+        assert!(!inner.is_opt());
+        assert_eq!(atom(inner.element()), (AtomType::I64, false));
+
+        // A matrix which may be null
+        let array = array_type("Option<&[&[u8]]>");
+        assert!(array.is_opt());
+        match array.element() {
+            ElementType::Array(inner) => {
+                assert!(!inner.is_opt());
+                assert_eq!(atom(inner.element()), (AtomType::U8, false));
+            }
+            _ => panic!("expected an array element"),
+        }
+
+        // Elements which are arrays that may be null
+        let array = array_type("Option<&[u8]>");
+        assert!(array.is_opt());
+        let element = ElementType::from_tokens(&tokens("Option<&[u8]>"));
+        assert!(matches!(element, ElementType::Array(ref inner) if inner.is_opt()));
+    }
+
+    #[test]
+    fn object_elements() {
+        let array = array_type("{ id: u32, label, score: Option<f64>, name: Cow<'a, str>, }");
+        let fields = match array.element() {
+            ElementType::Object(fields) => fields,
+            _ => panic!("expected an object element"),
+        };
+
+        let names = fields
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, ["id", "label", "score", "name"]);
+        assert_eq!(atom(&fields[0].1), (AtomType::U32, false));
+        // Untyped fields are &str
+        assert_eq!(atom(&fields[1].1), (AtomType::Str, false));
+        assert_eq!(atom(&fields[2].1), (AtomType::F64, true));
+        assert_eq!(atom(&fields[3].1), (AtomType::OwnedStr, false));
+    }
+
+    #[test]
+    fn nested_object_elements() {
+        let array = array_type("{ tags: &[&str], owner: { id: u64 } }");
+        let fields = match array.element() {
+            ElementType::Object(fields) => fields,
+            _ => panic!("expected an object element"),
+        };
+
+        assert!(matches!(fields[0].1, ElementType::Array(_)));
+        match &fields[1].1 {
+            ElementType::Object(owner) => assert_eq!(atom(&owner[0].1), (AtomType::U64, false)),
+            _ => panic!("expected an object field"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one field")]
+    fn empty_object_element() {
+        array_type("{}");
+    }
+
+    #[test]
+    fn string_types() {
+        let string_type = |ty: &str| string_type(&tokens(ty));
+
+        assert_eq!(string_type("&str"), Some(AtomType::Str));
+        assert_eq!(string_type("&'a str"), Some(AtomType::Str));
+        assert_eq!(string_type("&'static str"), Some(AtomType::Str));
+
+        for ty in [
+            "String",
+            "&String",
+            "&'a String",
+            "Box<str>",
+            "Arc<str>",
+            "Rc<str>",
+            "Cow<str>",
+            "Cow<'a, str>",
+            "&Cow<'static, str>",
+            "std::sync::Arc<str>",
+            "std::borrow::Cow<'a, str>",
+            "alloc::string::String",
+        ] {
+            assert_eq!(string_type(ty), Some(AtomType::OwnedStr), "{}", ty);
         }
     }
 
-    false
+    #[test]
+    fn not_string_types() {
+        let string_type = |ty: &str| string_type(&tokens(ty));
+
+        // A bare str isn't a value
+        assert_eq!(string_type("str"), None);
+        assert_eq!(string_type("Arc<String>"), None);
+        assert_eq!(string_type("Box<[u8]>"), None);
+        assert_eq!(string_type("Vec<u8>"), None);
+        assert_eq!(string_type("u64"), None);
+        assert_eq!(string_type("Option<&str>"), None);
+    }
+
+    #[test]
+    fn optional_string_types() {
+        let entry = entry_type("Option<&str>");
+        assert!(entry.is_opt() && entry.is_str() && !entry.is_owned_str());
+
+        let entry = entry_type("Option<Cow<'a, str>>");
+        assert!(entry.is_opt() && entry.is_owned_str());
+
+        // Untyped entries are &str expressions
+        assert!(!EntryType::new().is_owned_str());
+    }
+
+    #[test]
+    #[should_panic(expected = "unrecognised type")]
+    fn option_of_slice_entry() {
+        // Optional arrays are declared in [ ] entries, not as entry types
+        entry_type("Option<&[u32]>");
+    }
+
+    #[test]
+    fn large_integers() {
+        assert_eq!(entry_type("u128").atom_type(), ("Uint128", None));
+        assert_eq!(entry_type("i128").atom_type(), ("Int128", None));
+        assert_eq!(entry_type("usize").atom_type(), ("Uint", Some("u64")));
+        assert_eq!(entry_type("isize").atom_type(), ("Int", Some("i64")));
+
+        let entry = entry_type("Option<u128>");
+        assert!(entry.is_opt());
+        assert_eq!(entry.atom_type(), ("Uint128", None));
+    }
+
+    #[test]
+    fn integers_as_str() {
+        assert_eq!(entry_type("u128 as str").atom_type(), ("StrUint", None));
+        assert_eq!(entry_type("i128 as str").atom_type(), ("StrInt", None));
+        assert_eq!(
+            entry_type("u64 as str").atom_type(),
+            ("StrUint", Some("u128"))
+        );
+        assert_eq!(
+            entry_type("usize as str").atom_type(),
+            ("StrUint", Some("u128"))
+        );
+        assert_eq!(
+            entry_type("i8 as str").atom_type(),
+            ("StrInt", Some("i128"))
+        );
+        assert_eq!(
+            entry_type("isize as str").atom_type(),
+            ("StrInt", Some("i128"))
+        );
+
+        let entry = entry_type("Option<u64> as str");
+        assert!(entry.is_opt() && !entry.is_str());
+        assert_eq!(entry.atom_type(), ("StrUint", Some("u128")));
+    }
+
+    #[test]
+    #[should_panic(expected = "only integer types may be written as str")]
+    fn float_as_str() {
+        entry_type("f64 as str");
+    }
+
+    #[test]
+    #[should_panic(expected = "only integer types may be written as str")]
+    fn string_as_str() {
+        entry_type("String as str");
+    }
 }
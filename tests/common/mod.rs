@@ -0,0 +1,9 @@
+use ndjsonloggercore::MemorySink;
+
+// lines parses the lines written to sink, each test logs to its own sink
+pub fn lines(sink: &MemorySink) -> Vec<serde_json::Value> {
+    sink.lines()
+        .iter()
+        .map(|line| serde_json::from_str(line).expect("invalid json line"))
+        .collect()
+}
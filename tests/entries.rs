@@ -0,0 +1,174 @@
+// These tests need info lines, which the max level features may compile out
+#![cfg(not(any(
+    feature = "max_level_off",
+    feature = "max_level_error",
+    feature = "max_level_warn",
+    feature = "release_max_level_off",
+    feature = "release_max_level_error",
+    feature = "release_max_level_warn"
+)))]
+
+use ndjsonlogger::info;
+use ndjsonloggercore::MemorySink;
+
+mod common;
+use common::lines;
+
+#[test]
+fn nested_object() {
+    static SINK: MemorySink = MemorySink::new();
+
+    let method = "GET";
+    let host = "example.com";
+    info!(sink = SINK, "request served", {
+        http = {
+            method,
+            status: u16 = 200,
+            headers = { host }
+        }
+    });
+
+    assert_eq!(
+        lines(&SINK)[0]["http"],
+        serde_json::json!({"method": "GET", "status": 200, "headers": {"host": "example.com"}})
+    );
+}
+
+#[test]
+fn block_expression_value() {
+    static SINK: MemorySink = MemorySink::new();
+
+    let express = true;
+    info!(sink = SINK, "order shipped", {
+        reason = { if express { "express" } else { "standard" } },
+        carrier: &str = { "acme" }
+    });
+
+    let line = &lines(&SINK)[0];
+    assert_eq!(line["reason"], "express");
+    assert_eq!(line["carrier"], "acme");
+}
+
+#[test]
+fn optional_arrays() {
+    static SINK: MemorySink = MemorySink::new();
+
+    let retries = [Some(1_u32), None];
+    let names = [Some("alice"), None];
+    let tags: Option<&[&str]> = None;
+    let scores: Option<&[f64]> = Some(&[0.5]);
+    info!(sink = SINK, "arrays", {
+        [retries : Option<u32>     = retries],
+        [names   : Option<&str>    = names],
+        [tags    : Option<&[&str]> = tags],
+        [scores  : Option<&[f64]>  = scores]
+    });
+
+    let line = &lines(&SINK)[0];
+    assert_eq!(line["retries"], serde_json::json!([1, null]));
+    assert_eq!(line["names"], serde_json::json!(["alice", null]));
+    assert_eq!(line["tags"], serde_json::Value::Null);
+    assert_eq!(line["scores"], serde_json::json!([0.5]));
+}
+
+#[test]
+fn nested_arrays() {
+    static SINK: MemorySink = MemorySink::new();
+
+    struct Hit<'a> {
+        id: u32,
+        score: f64,
+        label: &'a str,
+    }
+
+    let matrix = [[1.0, 2.0], [3.0, 4.0]];
+    let hits = [Hit {
+        id: 7,
+        score: 0.5,
+        label: "exact",
+    }];
+    let no_rows: Option<&[&[u8]]> = None;
+    info!(sink = SINK, "search done", {
+        [matrix  : &[f64]                         = matrix],
+        [results : { id: u32, score: f64, label } = hits],
+        [no_rows : Option<&[&[u8]]>               = no_rows]
+    });
+
+    let line = &lines(&SINK)[0];
+    assert_eq!(line["matrix"], serde_json::json!([[1.0, 2.0], [3.0, 4.0]]));
+    assert_eq!(
+        line["results"],
+        serde_json::json!([{"id": 7, "score": 0.5, "label": "exact"}])
+    );
+    assert_eq!(line["no_rows"], serde_json::Value::Null);
+}
+
+#[test]
+fn large_integers() {
+    static SINK: MemorySink = MemorySink::new();
+
+    let order_id = u128::MAX;
+    let small: i128 = -12;
+    let trace_id: u64 = 42;
+    let offset: isize = -3;
+    let parent: Option<u64> = Some(7);
+    info!(sink = SINK, "order placed", {
+        order_id : u128               = order_id,
+        small    : i128               = small,
+        trace_id : u64 as str         = trace_id,
+        offset   : isize              = offset,
+        parent   : Option<u64> as str = parent,
+        refund   : u128 as str        = 5
+    });
+
+    let line = &lines(&SINK)[0];
+    assert_eq!(line["order_id"], "340282366920938463463374607431768211455");
+    assert_eq!(line["small"], -12);
+    assert_eq!(line["trace_id"], "42");
+    assert_eq!(line["offset"], -3);
+    assert_eq!(line["parent"], "7");
+    assert_eq!(line["refund"], "5");
+}
+
+#[test]
+fn string_types() {
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    static SINK: MemorySink = MemorySink::new();
+
+    let user = String::from("alice");
+    let region: Arc<str> = "eu-west-1".into();
+    let zone = Arc::new(String::from("eu-west-1a"));
+    let nick: Option<Cow<'static, str>> = Some(Cow::Borrowed("al"));
+    let roles = [String::from("admin")];
+    info!(sink = SINK, "login", {
+        user   : String                     = user,
+        region : Arc<str>                   = region,
+        zone   = &zone,
+        nick   : Option<Cow<'static, str>>  = nick,
+        [roles : String                     = roles]
+    });
+
+    let line = &lines(&SINK)[0];
+    assert_eq!(line["user"], "alice");
+    assert_eq!(line["region"], "eu-west-1");
+    assert_eq!(line["zone"], "eu-west-1a");
+    assert_eq!(line["nick"], "al");
+    assert_eq!(line["roles"], serde_json::json!(["admin"]));
+}
+
+#[test]
+fn escaped_braces() {
+    static SINK: MemorySink = MemorySink::new();
+
+    let name = "db";
+    info!(sink = SINK, "config {{}} is empty");
+    info!(sink = SINK, "config {{{name}}} is empty");
+
+    let lines = lines(&SINK);
+    assert_eq!(lines[0]["msg"], "config {} is empty");
+    assert!(lines[0].get("msg_template").is_none());
+    assert_eq!(lines[1]["msg"], "config {db} is empty");
+    assert_eq!(lines[1]["msg_template"], "config {{{name}}} is empty");
+}
@@ -0,0 +1,76 @@
+// These tests need info lines, which the max level features may compile out
+#![cfg(not(any(
+    feature = "max_level_off",
+    feature = "max_level_error",
+    feature = "max_level_warn",
+    feature = "release_max_level_off",
+    feature = "release_max_level_error",
+    feature = "release_max_level_warn"
+)))]
+
+use ndjsonlogger::instrument;
+use ndjsonloggercore::{MemorySink, Sink};
+
+mod common;
+use common::lines;
+
+// #[instrument] lines go to the registered sink
+static SINK: MemorySink = MemorySink::new();
+
+struct TestSink;
+
+impl Sink for TestSink {
+    fn write_line(&self, line: &str) {
+        SINK.write_line(line);
+    }
+}
+
+#[instrument(fields(user_id))]
+fn load_user(user_id: u64, name: &str) -> Result<String, String> {
+    if user_id == 0 {
+        return Err(format!("no user {}", name));
+    }
+
+    Ok(name.to_string())
+}
+
+#[instrument(level = "warn")]
+fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[test]
+fn enter_and_exit_lines() {
+    ndjsonloggercore::set_sink(TestSink).expect("sink already set");
+
+    assert_eq!(load_user(7, "alice"), Ok(String::from("alice")));
+    assert!(load_user(0, "bob").is_err());
+    assert_eq!(add(1, 2), 3);
+
+    let lines = lines(&SINK);
+    assert_eq!(lines.len(), 6);
+
+    assert_eq!(lines[0]["level"], "info");
+    assert_eq!(lines[0]["msg"], "load_user");
+    assert_eq!(lines[0]["event"], "enter");
+    assert_eq!(lines[0]["user_id"], 7);
+    // Only the named fields are entries
+    assert!(lines[0].get("name").is_none());
+
+    assert_eq!(lines[1]["msg"], "load_user");
+    assert_eq!(lines[1]["event"], "exit");
+    assert_eq!(lines[1]["result"], "ok");
+    assert!(lines[1]["elapsed_us"].is_u64());
+
+    // return leaving the body early still logs the exit line
+    assert_eq!(lines[2]["user_id"], 0);
+    assert_eq!(lines[3]["event"], "exit");
+    assert_eq!(lines[3]["result"], "err");
+
+    assert_eq!(lines[4]["level"], "warn");
+    assert_eq!(lines[4]["msg"], "add");
+    assert_eq!(lines[4]["event"], "enter");
+    assert_eq!(lines[5]["event"], "exit");
+    // add doesn't return a Result
+    assert!(lines[5].get("result").is_none());
+}
@@ -0,0 +1,15 @@
+use ndjsonlogger::{debug, info, trace};
+
+#[test]
+fn compiled_out_expressions() {
+    // Without the trace feature trace! is compiled out, as are other levels
+    // with the max level features, and each must still be an expression
+    for n in 0..4_u32 {
+        match n {
+            0 => trace!(1, "zero"),
+            1 => debug!("one"),
+            2 => info!("two"),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// These tests need error lines, which the max level features may compile out
+#![cfg(not(any(feature = "max_level_off", feature = "release_max_level_off")))]
+
+use std::error::Error;
+
+use ndjsonlogger::{fields, log_err};
+use ndjsonloggercore::{LogResultExt, MemorySink, Sink};
+
+mod common;
+use common::lines;
+
+// .log_err lines go to the registered sink
+static SINK: MemorySink = MemorySink::new();
+
+struct TestSink;
+
+impl Sink for TestSink {
+    fn write_line(&self, line: &str) {
+        SINK.write_line(line);
+    }
+}
+
+#[test]
+fn context_and_boxed_errors() {
+    ndjsonloggercore::set_sink(TestSink).expect("sink already set");
+    let _guard = fields!({ request_id = "req-1" }).enter();
+
+    let _ = "x".parse::<u32>().log_err("parse failed");
+    let boxed: Result<(), Box<dyn Error>> = Err("boxed".into());
+    let _ = boxed.log_err("boxed failed");
+    let shared: Result<(), Box<dyn Error + Send + Sync>> = Err("shared".into());
+    let _ = shared.log_err("shared failed");
+    let _ = log_err!("x".parse::<u32>(), "macro failed");
+
+    let lines = lines(&SINK);
+    assert_eq!(lines.len(), 4);
+    for line in &lines {
+        assert_eq!(line["level"], "error");
+        assert_eq!(line["request_id"], "req-1");
+    }
+
+    assert_eq!(lines[0]["msg"], "parse failed");
+    assert_eq!(lines[0]["err"], "invalid digit found in string");
+    assert_eq!(lines[1]["err"], "boxed");
+    assert_eq!(lines[2]["err"], "shared");
+    assert_eq!(lines[3]["msg"], "macro failed");
+}
@@ -0,0 +1,41 @@
+// These tests need error lines, which the max level features may compile out
+#![cfg(not(any(feature = "max_level_off", feature = "release_max_level_off")))]
+
+use ndjsonlogger::install_panic_hook;
+use ndjsonloggercore::{MemorySink, Sink};
+
+mod common;
+use common::lines;
+
+static SINK: MemorySink = MemorySink::new();
+
+struct TestSink;
+
+impl Sink for TestSink {
+    fn write_line(&self, line: &str) {
+        SINK.write_line(line);
+    }
+}
+
+#[test]
+fn panic_line() {
+    ndjsonloggercore::set_sink(TestSink).expect("sink already set");
+    install_panic_hook!();
+
+    let panic_line = line!() + 1;
+    let result = std::panic::catch_unwind(|| panic!("boom"));
+    assert!(result.is_err());
+
+    let raw_lines = SINK.lines();
+    assert_eq!(raw_lines.len(), 1);
+    // With the location features the line still has a single file and line,
+    // those of the panic
+    assert_eq!(raw_lines[0].matches("\"file\":").count(), 1);
+    assert_eq!(raw_lines[0].matches("\"line\":").count(), 1);
+
+    let line = &lines(&SINK)[0];
+    assert_eq!(line["level"], "error");
+    assert_eq!(line["msg"], "boom");
+    assert_eq!(line["file"], file!());
+    assert_eq!(line["line"], panic_line);
+}
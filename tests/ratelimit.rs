@@ -0,0 +1,69 @@
+// These tests need warn lines, which the max level features may compile out
+#![cfg(not(any(
+    feature = "max_level_off",
+    feature = "max_level_error",
+    feature = "release_max_level_off",
+    feature = "release_max_level_error"
+)))]
+
+use ndjsonlogger::warn;
+use ndjsonloggercore::MemorySink;
+
+mod common;
+use common::lines;
+
+#[test]
+fn every() {
+    static SINK: MemorySink = MemorySink::new();
+
+    for item_id in 0..5_u64 {
+        warn!(sink = SINK, every = "1h", "queue is full", { item_id: u64 = item_id });
+    }
+
+    // The first line prints, the rest fall in the same period
+    let lines = lines(&SINK);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["item_id"], 0);
+    assert!(lines[0].get("suppressed").is_none());
+}
+
+#[test]
+fn first() {
+    static SINK: MemorySink = MemorySink::new();
+
+    for item_id in 0..5_u64 {
+        warn!(sink = SINK, first = 2, "queue is full", { item_id: u64 = item_id });
+    }
+
+    assert_eq!(lines(&SINK).len(), 2);
+}
+
+#[test]
+fn once() {
+    static SINK: MemorySink = MemorySink::new();
+
+    for _ in 0..3 {
+        warn!(sink = SINK, once, "disk almost full");
+    }
+
+    assert_eq!(lines(&SINK).len(), 1);
+}
+
+#[test]
+fn suppressed_count() {
+    static SINK: MemorySink = MemorySink::new();
+
+    for n in 0..6_u32 {
+        // Well past the period, the calls either side are well within it
+        if n == 3 {
+            std::thread::sleep(std::time::Duration::from_millis(600));
+        }
+        warn!(sink = SINK, every = "300ms", "queue is full");
+    }
+
+    // The line printed once the period has passed counts those suppressed
+    let lines = lines(&SINK);
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].get("suppressed").is_none());
+    assert_eq!(lines[1]["suppressed"], 2);
+}
@@ -0,0 +1,65 @@
+// These tests need info lines, which the max level features may compile out
+#![cfg(not(any(
+    feature = "max_level_off",
+    feature = "max_level_error",
+    feature = "max_level_warn",
+    feature = "release_max_level_off",
+    feature = "release_max_level_error",
+    feature = "release_max_level_warn"
+)))]
+
+use ndjsonlogger::info;
+use ndjsonloggercore::MemorySink;
+
+mod common;
+use common::lines;
+
+#[test]
+fn sample_all() {
+    static SINK: MemorySink = MemorySink::new();
+
+    for n in 0..10_u32 {
+        info!(sink = SINK, sample = 1.0, "cache lookup", { n: u32 = n });
+    }
+
+    let lines = lines(&SINK);
+    assert_eq!(lines.len(), 10);
+    for line in lines {
+        assert_eq!(line["sample_rate"], 1.0);
+    }
+}
+
+#[test]
+fn integer_rate() {
+    static SINK: MemorySink = MemorySink::new();
+
+    info!(sink = SINK, sample = 1, "cache lookup");
+
+    let lines = lines(&SINK);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["sample_rate"], 1.0);
+}
+
+#[test]
+fn sample_by_key() {
+    static SINK: MemorySink = MemorySink::new();
+
+    // Each key is logged twice, so is kept or dropped both times
+    for _ in 0..2 {
+        for trace_id in 0..64_u64 {
+            info!(sink = SINK, sample = 0.5, sample_by = trace_id, "cache lookup", {
+                trace_id: u64 = trace_id
+            });
+        }
+    }
+
+    let lines = lines(&SINK);
+    let mut counts = [0; 64];
+    for line in &lines {
+        assert_eq!(line["sample_rate"], 0.5);
+        counts[line["trace_id"].as_u64().unwrap() as usize] += 1;
+    }
+
+    assert!(counts.iter().all(|&count| count == 0 || count == 2));
+    assert!(!lines.is_empty() && lines.len() < 128);
+}
@@ -0,0 +1,35 @@
+// These tests need info lines, which the max level features may compile out
+#![cfg(not(any(
+    feature = "max_level_off",
+    feature = "max_level_error",
+    feature = "max_level_warn",
+    feature = "release_max_level_off",
+    feature = "release_max_level_error",
+    feature = "release_max_level_warn"
+)))]
+
+use ndjsonlogger::timed;
+use ndjsonloggercore::MemorySink;
+
+mod common;
+use common::lines;
+
+fn parse(sink: &'static MemorySink, s: &str) -> Result<u32, std::num::ParseIntError> {
+    // ? is applied to the value of timed!, so the line is logged on error
+    let n = timed!(info, sink = *sink, "parse", { s }, { s.parse::<u32>() })?;
+    Ok(n)
+}
+
+#[test]
+fn logs_when_block_errors() {
+    static SINK: MemorySink = MemorySink::new();
+
+    assert_eq!(parse(&SINK, "12"), Ok(12));
+    assert!(parse(&SINK, "x").is_err());
+
+    let lines = lines(&SINK);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["msg"], "parse");
+    assert_eq!(lines[1]["s"], "x");
+    assert!(lines[1]["elapsed_us"].is_u64());
+}